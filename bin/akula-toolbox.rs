@@ -213,6 +213,17 @@ async fn download_headers(
             consensus,
             max_block: u64::MAX.into(),
             increment: None,
+            request_limit: None,
+            build_header_number: true,
+            orphans: None,
+            penalize_workers: 1,
+            per_call_deadline: None,
+            retry_interval: None,
+            chain_tip: None,
+            checkpoints: Default::default(),
+            collect_seal_failures: false,
+            compute_td: true,
+            soft_unwind_depth: None,
         },
         false,
     );
@@ -1,7 +1,8 @@
 use akula::{
+    accessors::chain::storage_body::DEFAULT_MAX_BODY_RLP_BYTES,
     akula_tracing::{self, Component},
     binutil::AkulaDataDir,
-    consensus::{engine_factory, Consensus, ForkChoiceMode},
+    consensus::{engine_factory, Consensus, ExternalForkChoice, ForkChoiceMode},
     kv::tables::CHAINDATA_TABLES,
     models::*,
     p2p::node::NodeBuilder,
@@ -69,6 +70,10 @@ pub struct Opt {
     #[clap(long, default_value = "500000")]
     pub sender_recovery_batch_size: u64,
 
+    /// Number of threads to recover senders on. Shared with other stages if not set.
+    #[clap(long)]
+    pub sender_recovery_threads: Option<usize>,
+
     /// Execution batch size (Ggas).
     #[clap(long, default_value = "5000")]
     pub execution_batch_size: u64,
@@ -93,6 +98,31 @@ pub struct Opt {
     #[clap(long, default_value = "0")]
     pub delay_after_sync: u64,
 
+    /// Number of header segments received ahead of the requested range to keep for later linking.
+    #[clap(long, default_value = "0")]
+    pub max_orphan_headers: usize,
+
+    /// Number of tasks penalizing misbehaving peers during header download.
+    #[clap(long, default_value = "1")]
+    pub penalize_workers: usize,
+
+    /// Minimum time before an unanswered header request is sent again (seconds). Rebroadcast on every check if 0.
+    #[clap(long, default_value = "0")]
+    pub header_retry_interval: u64,
+
+    /// Keep headers on unwinds up to this many blocks deep, so they are not downloaded again
+    /// if the unwound chain wins. Disabled if 0.
+    #[clap(long, default_value = "0")]
+    pub header_soft_unwind_depth: u64,
+
+    /// Known block hash to check downloaded headers against, as `<number>:<hash>`. May be repeated.
+    #[clap(long, value_parser(parse_checkpoint))]
+    pub header_checkpoint: Vec<(BlockNumber, H256)>,
+
+    /// Maximum RLP encoded size of a downloaded block body (bytes).
+    #[clap(long, default_value_t = DEFAULT_MAX_BODY_RLP_BYTES)]
+    pub max_body_size: usize,
+
     /// Disable JSONRPC.
     #[clap(long)]
     pub no_rpc: bool,
@@ -118,6 +148,13 @@ pub struct Opt {
     pub jwt_secret_path: Option<ExpandedPathBuf>,
 }
 
+fn parse_checkpoint(s: &str) -> anyhow::Result<(BlockNumber, H256)> {
+    let (number, hash) = s
+        .split_once(':')
+        .ok_or_else(|| anyhow::format_err!("expected <number>:<hash>"))?;
+    Ok((number.parse::<u64>()?.into(), hash.parse()?))
+}
+
 #[allow(unreachable_code)]
 fn main() -> anyhow::Result<()> {
     let opt: Opt = Opt::parse();
@@ -212,6 +249,8 @@ fn main() -> anyhow::Result<()> {
 
                 let chain_config = ChainConfig::from(chainspec);
 
+                HeaderDownload::verify_genesis(&db.begin()?, chain_config.genesis_hash)?;
+
                 if !opt.no_rpc {
                     tokio::spawn({
                         let db = db.clone();
@@ -381,6 +420,23 @@ fn main() -> anyhow::Result<()> {
                 let tip_discovery =
                     !matches!(consensus.fork_choice_mode(), ForkChoiceMode::External(_));
 
+                // Forward fork choice received over engine API to the node
+                if let ForkChoiceMode::External(mut fork_choice) = consensus.fork_choice_mode() {
+                    tokio::spawn({
+                        let node = node.clone();
+                        async move {
+                            while fork_choice.changed().await.is_ok() {
+                                let ExternalForkChoice {
+                                    head_block,
+                                    safe_block,
+                                    finalized_block,
+                                } = *fork_choice.borrow();
+                                node.set_forkchoice(head_block, safe_block, finalized_block);
+                            }
+                        }
+                    });
+                }
+
                 tokio::spawn({
                     let node = node.clone();
                     async move {
@@ -394,6 +450,20 @@ fn main() -> anyhow::Result<()> {
                         consensus: consensus.clone(),
                         max_block: opt.max_block.unwrap_or_else(|| u64::MAX.into()),
                         increment: opt.increment,
+                        request_limit: None,
+                        build_header_number: true,
+                        orphans: (opt.max_orphan_headers > 0)
+                            .then(|| Arc::new(OrphanPool::new(opt.max_orphan_headers))),
+                        penalize_workers: opt.penalize_workers,
+                        per_call_deadline: None,
+                        retry_interval: (opt.header_retry_interval > 0)
+                            .then(|| Duration::from_secs(opt.header_retry_interval)),
+                        chain_tip: None,
+                        checkpoints: opt.header_checkpoint.iter().copied().collect(),
+                        collect_seal_failures: false,
+                        compute_td: true,
+                        soft_unwind_depth: (opt.header_soft_unwind_depth > 0)
+                            .then_some(opt.header_soft_unwind_depth),
                     },
                     false,
                 );
@@ -404,11 +474,28 @@ fn main() -> anyhow::Result<()> {
                     },
                     false,
                 );
-                staged_sync.push(BodyDownload { node, consensus }, false);
+                staged_sync.push(
+                    BodyDownload {
+                        node,
+                        consensus,
+                        max_body_rlp_bytes: opt.max_body_size,
+                    },
+                    false,
+                );
                 staged_sync.push(TotalTxIndex, false);
                 staged_sync.push(
                     SenderRecovery {
                         batch_size: opt.sender_recovery_batch_size.try_into().unwrap(),
+                        thread_pool: opt
+                            .sender_recovery_threads
+                            .map(|num_threads| {
+                                rayon::ThreadPoolBuilder::new()
+                                    .num_threads(num_threads)
+                                    .thread_name(|i| format!("sender-recovery-{i}"))
+                                    .build()
+                                    .map(Arc::new)
+                            })
+                            .transpose()?,
                     },
                     false,
                 );
@@ -1,7 +1,18 @@
 use crate::{
-    kv::{mdbx::*, tables},
+    consensus::BlockRewardSchedule,
+    crypto::keccak256,
+    kv::{
+        mdbx::*,
+        tables::{self, ErasedTable},
+        traits::{TableDecode, TryGenIter},
+    },
     models::*,
+    p2p::types::BlockHashAndNumber,
 };
+use anyhow::format_err;
+use bytes::BytesMut;
+use fastrlp::Encodable;
+use sha3::{Digest, Keccak256};
 use tracing::*;
 
 pub mod canonical_hash {
@@ -16,6 +27,35 @@ pub mod canonical_hash {
 
         tx.get(tables::CanonicalHeader, number)
     }
+
+    /// Canonical hashes of several blocks in one ordered pass over the table, aligned with `numbers`.
+    /// Blocks without canonical hash are `None`.
+    pub fn read_many<K: TransactionKind, E: EnvironmentKind>(
+        tx: &MdbxTransaction<'_, K, E>,
+        numbers: &[BlockNumber],
+    ) -> anyhow::Result<Vec<Option<H256>>> {
+        trace!("Reading canonical hashes for {} blocks", numbers.len());
+
+        let mut order = (0..numbers.len()).collect::<Vec<_>>();
+        order.sort_unstable_by_key(|&i| numbers[i]);
+
+        let mut out = vec![None; numbers.len()];
+        if let Some(&lowest) = order.first() {
+            let mut walker = tx
+                .cursor(tables::CanonicalHeader)?
+                .walk(Some(numbers[lowest]));
+            let mut entry = walker.next().transpose()?;
+            for i in order {
+                let number = numbers[i];
+                while matches!(entry, Some((n, _)) if n < number) {
+                    entry = walker.next().transpose()?;
+                }
+                out[i] = entry.filter(|&(n, _)| n == number).map(|(_, hash)| hash);
+            }
+        }
+
+        Ok(out)
+    }
 }
 
 pub mod header_number {
@@ -55,6 +95,63 @@ pub mod header {
 
         tx.get(tables::Header, number)
     }
+
+    /// Check that the stored header of a canonical block hashes to its canonical hash.
+    /// Returns `false` if either is missing.
+    ///
+    /// Costs one header decode, RLP encoding and Keccak-256 over it (~0.5 KiB).
+    pub fn stored_header_hash<K: TransactionKind, E: EnvironmentKind>(
+        tx: &MdbxTransaction<'_, K, E>,
+        number: impl Into<BlockNumber>,
+    ) -> anyhow::Result<bool> {
+        let number = number.into();
+        trace!("Verifying stored header hash for block number {}", number);
+
+        if let Some(canonical_hash) = tx.get(tables::CanonicalHeader, number)? {
+            if let Some(header) = tx.get(tables::Header, number)? {
+                return Ok(header.hash() == canonical_hash);
+            }
+        }
+
+        Ok(false)
+    }
+
+    /// Read canonical headers in `from..=to` in one forward pass, stopping at the first gap.
+    pub fn read_canonical_range<K: TransactionKind, E: EnvironmentKind>(
+        tx: &MdbxTransaction<'_, K, E>,
+        from: impl Into<BlockNumber>,
+        to: impl Into<BlockNumber>,
+    ) -> anyhow::Result<Vec<(BlockNumber, BlockHeader)>> {
+        let from = from.into();
+        let to = to.into();
+        trace!("Reading canonical headers from {} to {}", from, to);
+
+        let mut header_cursor = tx.cursor(tables::Header)?;
+        let mut canonical = tx.cursor(tables::CanonicalHeader)?.walk(Some(from));
+
+        let mut out = vec![];
+        let mut expected = from;
+        while expected <= to {
+            match canonical.next().transpose()? {
+                Some((number, _)) if number == expected => {}
+                _ => break,
+            }
+
+            let header = if out.is_empty() {
+                header_cursor.seek_exact(expected)?
+            } else {
+                header_cursor.next()?
+            };
+            match header {
+                Some((number, header)) if number == expected => out.push((number, header)),
+                _ => break,
+            }
+
+            expected += 1_u8;
+        }
+
+        Ok(out)
+    }
 }
 
 pub mod tx {
@@ -103,6 +200,96 @@ pub mod tx {
 
         Ok(())
     }
+
+    /// Same as `write`, but fails without writing anything if any id in the target range is already taken.
+    pub fn write_checked<E: EnvironmentKind>(
+        tx: &MdbxTransaction<'_, RW, E>,
+        base_tx_id: impl Into<TxIndex>,
+        txs: &[MessageWithSignature],
+    ) -> anyhow::Result<()> {
+        let base_tx_id = base_tx_id.into();
+
+        if !txs.is_empty() {
+            let end = base_tx_id + txs.len() as u64;
+            if let Some((taken, _)) = tx.cursor(tables::BlockTransaction)?.seek(base_tx_id)? {
+                if taken < end {
+                    anyhow::bail!(
+                        "transaction id {taken} in range {base_tx_id}..{end} is already taken, next free id is {}",
+                        super::storage_body::next_base_tx_id(tx)?
+                    );
+                }
+            }
+        }
+
+        write(tx, base_tx_id, txs)
+    }
+
+    /// Stream transactions of blocks `from..=to` in order, along with their block numbers and ids.
+    /// Blocks without a stored body are skipped if `skip_missing` is set, otherwise an error is yielded.
+    pub fn stream_range<'db, 'tx, K: TransactionKind, E: EnvironmentKind>(
+        tx: &'tx MdbxTransaction<'db, K, E>,
+        from: impl Into<BlockNumber>,
+        to: impl Into<BlockNumber>,
+        skip_missing: bool,
+    ) -> impl Iterator<Item = anyhow::Result<(BlockNumber, TxIndex, MessageWithSignature)>> + 'tx
+    where
+        'db: 'tx,
+    {
+        let from = from.into();
+        let to = to.into();
+        trace!("Streaming transactions from block {} to {}", from, to);
+
+        TryGenIter::from(move || {
+            let mut body_cursor = tx.cursor(tables::BlockBody)?;
+            let mut tx_cursor = tx.cursor(tables::BlockTransaction)?;
+
+            for block_number in from..=to {
+                let body = if let Some((_, body)) = body_cursor.seek_exact(block_number)? {
+                    body
+                } else if skip_missing {
+                    warn!("Body for block {} not found, skipping", block_number);
+                    continue;
+                } else {
+                    return Err(format_err!("body for block {} not found", block_number));
+                };
+
+                for i in 0..body.tx_amount {
+                    let tx_id = body.base_tx_id + i;
+                    let (_, transaction) = tx_cursor.seek_exact(tx_id)?.ok_or_else(|| {
+                        format_err!("transaction {} of block {} not found", tx_id, block_number)
+                    })?;
+
+                    yield (block_number, tx_id, transaction);
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Delete `amount` transactions starting from `base_tx_id`, skipping already missing ones.
+    pub fn delete_range<E: EnvironmentKind>(
+        tx: &MdbxTransaction<'_, RW, E>,
+        base_tx_id: impl Into<TxIndex>,
+        amount: u64,
+    ) -> anyhow::Result<()> {
+        let base_tx_id = base_tx_id.into();
+        trace!(
+            "Deleting {} transactions starting from {}",
+            amount,
+            base_tx_id
+        );
+
+        let mut cursor = tx.cursor(tables::BlockTransaction)?;
+
+        for i in 0..amount {
+            if cursor.seek_exact(base_tx_id + i)?.is_some() {
+                cursor.delete_current()?;
+            }
+        }
+
+        Ok(())
+    }
 }
 
 pub mod tx_sender {
@@ -135,11 +322,38 @@ pub mod tx_sender {
 
         Ok(())
     }
+
+    /// Delete transaction senders for `amount` blocks starting from `number`, skipping already missing ones.
+    pub fn delete_range<E: EnvironmentKind>(
+        tx: &MdbxTransaction<'_, RW, E>,
+        number: impl Into<BlockNumber>,
+        amount: u64,
+    ) -> anyhow::Result<()> {
+        let number = number.into();
+        trace!(
+            "Deleting transaction senders for {} blocks starting from {}",
+            amount,
+            number
+        );
+
+        let mut cursor = tx.cursor(tables::TxSender)?;
+
+        for i in 0..amount {
+            if cursor.seek_exact(number + i)?.is_some() {
+                cursor.delete_current()?;
+            }
+        }
+
+        Ok(())
+    }
 }
 
 pub mod storage_body {
     use super::*;
 
+    /// Default limit on RLP encoded size of a block body accepted from the network.
+    pub const DEFAULT_MAX_BODY_RLP_BYTES: usize = 32 << 20;
+
     pub fn read<K, E>(
         tx: &MdbxTransaction<'_, K, E>,
         number: impl Into<BlockNumber>,
@@ -151,7 +365,12 @@ pub mod storage_body {
         let number = number.into();
         trace!("Reading storage body for block {number}");
 
-        tx.get(tables::BlockBody, number)
+        if let Some(mut body) = tx.get(tables::BlockBody, number)? {
+            body.withdrawals = tx.get(tables::BlockWithdrawals, number)?;
+            return Ok(Some(body));
+        }
+
+        Ok(None)
     }
 
     pub fn write<E>(
@@ -166,9 +385,77 @@ pub mod storage_body {
         trace!("Writing storage body for block {number}");
 
         tx.set(tables::BlockBody, number, body.clone())?;
+        if let Some(withdrawals) = &body.withdrawals {
+            tx.set(tables::BlockWithdrawals, number, withdrawals.clone())?;
+        } else {
+            tx.del(tables::BlockWithdrawals, number, None)?;
+        }
+
+        Ok(())
+    }
+
+    /// First transaction id after those of the last stored body.
+    pub fn next_base_tx_id<K, E>(tx: &MdbxTransaction<'_, K, E>) -> anyhow::Result<TxIndex>
+    where
+        K: TransactionKind,
+        E: EnvironmentKind,
+    {
+        Ok(tx
+            .cursor(tables::BlockBody)?
+            .last()?
+            .map(|(_, body)| body.base_tx_id + body.tx_amount)
+            .unwrap_or(TxIndex(0)))
+    }
+
+    /// Fail if RLP encoding of the body is larger than `max_rlp_bytes`.
+    pub fn check_bounded(
+        hash: H256,
+        number: BlockNumber,
+        body: &BlockBody,
+        max_rlp_bytes: usize,
+    ) -> anyhow::Result<()> {
+        let rlp_bytes = body.length();
+        if rlp_bytes > max_rlp_bytes {
+            anyhow::bail!(
+                "body of block #{number} ({hash:?}) is {rlp_bytes} bytes, over the limit of {max_rlp_bytes}"
+            );
+        }
 
         Ok(())
     }
+
+    /// Write block body along with its transactions, starting at `base_tx_id`, and withdrawals,
+    /// replacing stored ones like [write] does. Withdrawals do not count towards the limit.
+    /// Nothing is written if RLP encoding of the body is larger than `max_rlp_bytes`.
+    pub fn write_bounded<E>(
+        tx: &MdbxTransaction<'_, RW, E>,
+        hash: H256,
+        number: impl Into<BlockNumber>,
+        base_tx_id: impl Into<TxIndex>,
+        body: &BlockBody,
+        withdrawals: Option<Vec<Withdrawal>>,
+        max_rlp_bytes: usize,
+    ) -> anyhow::Result<()>
+    where
+        E: EnvironmentKind,
+    {
+        let number = number.into();
+        let base_tx_id = base_tx_id.into();
+
+        check_bounded(hash, number, body, max_rlp_bytes)?;
+
+        write(
+            tx,
+            number,
+            &BodyForStorage {
+                base_tx_id,
+                tx_amount: body.transactions.len() as u64,
+                ommers: body.ommers.clone(),
+                withdrawals,
+            },
+        )?;
+        super::tx::write(tx, base_tx_id, &body.transactions)
+    }
 }
 
 pub mod block_body {
@@ -230,6 +517,67 @@ pub mod block_body {
     }
 }
 
+pub mod block {
+    use super::*;
+
+    /// Stored header, body and transactions of the block, along with transaction senders.
+    /// Returns `None` if there is no header with this hash at `number`,
+    /// fails if the header is stored but the body or some senders are missing.
+    pub fn read_with_senders<K: TransactionKind, E: EnvironmentKind>(
+        tx: &MdbxTransaction<'_, K, E>,
+        hash: H256,
+        number: impl Into<BlockNumber>,
+    ) -> anyhow::Result<Option<BlockWithSenders>> {
+        let number = number.into();
+        trace!("Reading block {number}/{hash:?} with senders");
+
+        let header = match super::header::read(tx, number)? {
+            Some(header) if header.hash() == hash => header,
+            _ => return Ok(None),
+        };
+        let body = super::storage_body::read(tx, number)?
+            .ok_or_else(|| format_err!("no body for block #{number}/{hash:?}"))?;
+        let transactions = super::tx::read(tx, body.base_tx_id, body.tx_amount.try_into()?)?;
+        let senders = super::tx_sender::read(tx, number)?;
+        if transactions.len() as u64 != body.tx_amount || senders.len() != transactions.len() {
+            anyhow::bail!(
+                "block #{number}/{hash:?} has {} transactions and {} senders stored, expected {}",
+                transactions.len(),
+                senders.len(),
+                body.tx_amount
+            );
+        }
+
+        Ok(Some(BlockWithSenders {
+            header,
+            transactions: transactions
+                .into_iter()
+                .zip(senders)
+                .map(|(tx, sender)| MessageWithSender {
+                    message: tx.message,
+                    sender,
+                })
+                .collect(),
+            ommers: body.ommers,
+        }))
+    }
+}
+
+pub mod withdrawals {
+    use super::*;
+
+    /// Withdrawals of the block, `None` for pre-Shanghai blocks.
+    pub fn read<K: TransactionKind, E: EnvironmentKind>(
+        tx: &MdbxTransaction<'_, K, E>,
+        number: impl Into<BlockNumber>,
+    ) -> anyhow::Result<Option<Vec<Withdrawal>>> {
+        let number = number.into();
+        trace!("Reading withdrawals for block {number}");
+
+        tx.get(tables::BlockWithdrawals, number)
+    }
+}
+
 pub mod td {
     use super::*;
 
@@ -242,6 +590,111 @@ pub mod td {
 
         tx.get(tables::HeadersTotalDifficulty, number)
     }
+
+    /// Total difficulty of canonical blocks `from..=to`, stopping at the end of the canonical chain.
+    /// Fails on the first canonical block without total difficulty.
+    pub fn read_range<K: TransactionKind, E: EnvironmentKind>(
+        tx: &MdbxTransaction<'_, K, E>,
+        from: impl Into<BlockNumber>,
+        to: impl Into<BlockNumber>,
+    ) -> anyhow::Result<Vec<(BlockNumber, U256)>> {
+        let from = from.into();
+        let to = to.into();
+        trace!("Reading total difficulty for blocks {from}..={to}");
+
+        let mut out = vec![];
+        let mut canonical = tx.cursor(tables::CanonicalHeader)?.walk(Some(from));
+        let mut tds = tx.cursor(tables::HeadersTotalDifficulty)?.walk(Some(from));
+        while let Some((number, _)) = canonical.next().transpose()? {
+            if number > to {
+                break;
+            }
+
+            let (_, td) = tds
+                .next()
+                .transpose()?
+                .filter(|&(td_number, _)| td_number == number)
+                .ok_or_else(|| format_err!("no total difficulty for block #{number}"))?;
+            out.push((number, td));
+        }
+
+        Ok(out)
+    }
+
+    /// Compute and write total difficulty of blocks `from..=to` from their headers,
+    /// on top of the stored total difficulty of the parent of `from`.
+    pub fn repair_range<E: EnvironmentKind>(
+        tx: &MdbxTransaction<'_, RW, E>,
+        from: impl Into<BlockNumber>,
+        to: impl Into<BlockNumber>,
+    ) -> anyhow::Result<()> {
+        let from = from.into();
+        let to = to.into();
+        trace!("Computing total difficulty for blocks {from}..={to}");
+
+        let mut td = if let Some(parent) = from.0.checked_sub(1).map(BlockNumber) {
+            read(tx, parent)?
+                .ok_or_else(|| format_err!("no total difficulty for block #{parent}"))?
+        } else {
+            U256::ZERO
+        };
+
+        let mut walker = tx.cursor(tables::Header)?.walk(Some(from));
+        for expected_number in from..=to {
+            let (_, header) = walker
+                .next()
+                .transpose()?
+                .filter(|(number, _)| *number == expected_number)
+                .ok_or_else(|| format_err!("no header for block #{expected_number}"))?;
+
+            td += header.difficulty;
+            tx.set(tables::HeadersTotalDifficulty, expected_number, td)?;
+        }
+
+        Ok(())
+    }
+
+    /// Find the first block in `(from, to]` whose total difficulty is not greater than its parent's.
+    /// Blocks with zero difficulty (post-merge) may keep total difficulty of the parent.
+    ///
+    /// Only compares stored totals, reading a header only when the total did not change.
+    pub fn assert_monotonic<K: TransactionKind, E: EnvironmentKind>(
+        tx: &MdbxTransaction<'_, K, E>,
+        from: impl Into<BlockNumber>,
+        to: impl Into<BlockNumber>,
+    ) -> anyhow::Result<Option<BlockNumber>> {
+        let from = from.into();
+        let to = to.into();
+        trace!("Checking total difficulty ordering for blocks {from}..={to}");
+
+        let mut parent = None;
+        let mut walker = tx.cursor(tables::HeadersTotalDifficulty)?.walk(Some(from));
+        for expected_number in from..=to {
+            let (number, td) = walker
+                .next()
+                .transpose()?
+                .filter(|&(number, _)| number == expected_number)
+                .ok_or_else(|| format_err!("no total difficulty for block #{expected_number}"))?;
+
+            if let Some(parent_td) = parent {
+                if td < parent_td {
+                    return Ok(Some(number));
+                }
+
+                if td == parent_td {
+                    let header = header::read(tx, number)?
+                        .ok_or_else(|| format_err!("no header for block #{number}"))?;
+                    if header.difficulty != U256::ZERO {
+                        return Ok(Some(number));
+                    }
+                }
+            }
+
+            parent = Some(td);
+        }
+
+        Ok(None)
+    }
 }
 
 pub mod tl {
@@ -273,78 +726,1631 @@ pub mod tl {
 
         Ok(())
     }
+
+    pub fn delete<E: EnvironmentKind>(
+        tx: &MdbxTransaction<'_, RW, E>,
+        tx_hash: H256,
+    ) -> anyhow::Result<()> {
+        trace!("Deleting tx_lookup for hash {}", tx_hash);
+
+        tx.del(tables::BlockTransactionLookup, tx_hash, None)?;
+
+        Ok(())
+    }
+
+    /// Lookup entries of transactions in blocks `from..=to`. The table is keyed by transaction hash,
+    /// so transactions are taken from stored bodies instead, and hashed. Blocks without a body are skipped.
+    pub fn walk_range<'db, 'tx, K: TransactionKind, E: EnvironmentKind>(
+        tx: &'tx MdbxTransaction<'db, K, E>,
+        from: impl Into<BlockNumber>,
+        to: impl Into<BlockNumber>,
+    ) -> impl Iterator<Item = anyhow::Result<(H256, BlockNumber)>> + 'tx
+    where
+        'db: 'tx,
+    {
+        let from = from.into();
+        let to = to.into();
+
+        TryGenIter::from(move || {
+            let mut lookup_cursor = tx.cursor(tables::BlockTransactionLookup)?;
+            for res in super::tx::stream_range(tx, from, to, true) {
+                let (block_number, _, transaction) = res?;
+                let tx_hash = transaction.hash();
+                if let Some((_, indexed)) = lookup_cursor.seek_exact(tx_hash)? {
+                    if indexed.0 == block_number {
+                        yield (tx_hash, block_number);
+                    }
+                }
+            }
+
+            Ok(())
+        })
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::kv::new_mem_chaindata;
-    use bytes::Bytes;
+/// Number and hash of the last block of the canonical chain, `None` if the chain is empty.
+pub fn canonical_tip<K: TransactionKind, E: EnvironmentKind>(
+    tx: &MdbxTransaction<'_, K, E>,
+) -> anyhow::Result<Option<BlockHashAndNumber>> {
+    trace!("Reading canonical tip");
 
-    #[test]
-    fn accessors() {
-        let tx1 = MessageWithSignature {
-            message: Message::Legacy {
-                chain_id: None,
-                nonce: 1,
-                gas_price: 20_000.as_u256(),
-                gas_limit: 3_000_000,
-                action: TransactionAction::Create,
-                value: 0.as_u256(),
-                input: Bytes::new(),
-            },
-            signature: MessageSignature::new(false, H256::repeat_byte(2), H256::repeat_byte(3))
-                .unwrap(),
-        };
-        let tx2 = MessageWithSignature {
-            message: Message::Legacy {
-                chain_id: None,
-                nonce: 2,
-                gas_price: 30_000.as_u256(),
-                gas_limit: 1_000_000,
-                action: TransactionAction::Create,
-                value: 10.as_u256(),
-                input: Bytes::new(),
-            },
-            signature: MessageSignature::new(true, H256::repeat_byte(6), H256::repeat_byte(9))
-                .unwrap(),
-        };
-        let txs = [tx1, tx2];
+    Ok(tx
+        .cursor(tables::CanonicalHeader)?
+        .last()?
+        .map(|(number, hash)| BlockHashAndNumber { hash, number }))
+}
 
-        let sender1 = Address::random();
-        let sender2 = Address::random();
-        let senders = [sender1, sender2];
+/// Locate a transaction through the lookup index: number and canonical hash of its block,
+/// and its index within the block. Returns `None` if the transaction is not indexed.
+pub fn find_block_of_tx<K: TransactionKind, E: EnvironmentKind>(
+    tx: &MdbxTransaction<'_, K, E>,
+    tx_hash: H256,
+) -> anyhow::Result<Option<(BlockNumber, H256, u32)>> {
+    trace!("Finding block of tx {tx_hash:?}");
 
-        let block1_hash = H256::random();
-        let body = BodyForStorage {
-            base_tx_id: 1.into(),
-            tx_amount: 2,
-            ommers: Default::default(),
-        };
+    let block_number = if let Some(block_number) = tl::read(tx, tx_hash)? {
+        block_number
+    } else {
+        return Ok(None);
+    };
+    let block_hash = canonical_hash::read(tx, block_number)?
+        .ok_or_else(|| format_err!("no canonical hash for block #{block_number}"))?;
+    let body = storage_body::read(tx, block_number)?
+        .ok_or_else(|| format_err!("no body for block #{block_number}/{block_hash:?}"))?;
 
-        let db = new_mem_chaindata().unwrap();
-        let rwtx = db.begin_mutable().unwrap();
-        let rwtx = &rwtx;
+    let mut walker = tx
+        .cursor(tables::BlockTransaction)?
+        .walk(Some(body.base_tx_id));
+    for index in 0..body.tx_amount {
+        let (_, transaction) = walker
+            .next()
+            .transpose()?
+            .filter(|(tx_id, _)| *tx_id == body.base_tx_id + index)
+            .ok_or_else(|| {
+                format_err!(
+                    "missing transaction {} of block #{block_number}",
+                    body.base_tx_id + index
+                )
+            })?;
+        if transaction.hash() == tx_hash {
+            return Ok(Some((block_number, block_hash, index.try_into()?)));
+        }
+    }
 
-        storage_body::write(rwtx, 1, &body).unwrap();
-        rwtx.set(tables::CanonicalHeader, 1.into(), block1_hash)
-            .unwrap();
-        tx::write(rwtx, 1, &txs).unwrap();
-        tx_sender::write(rwtx, 1, senders.to_vec()).unwrap();
+    anyhow::bail!(
+        "tx {tx_hash:?} not found in block #{block_number}/{block_hash:?}, lookup index is invalid"
+    )
+}
 
-        let recovered_body = storage_body::read(rwtx, 1)
-            .unwrap()
-            .expect("Could not recover storage body.");
-        let recovered_hash = rwtx
-            .get(tables::CanonicalHeader, 1.into())
-            .unwrap()
-            .expect("Could not recover block hash");
-        let recovered_txs = tx::read(rwtx, 1, 2).unwrap();
-        let recovered_senders = tx_sender::read(rwtx, 1).unwrap();
+/// Transactions trie root of the stored body of the block, to be checked against
+/// `transactions_root` of its header. Returns `None` if the block has no body.
+pub fn compute_transactions_root<K: TransactionKind, E: EnvironmentKind>(
+    tx: &MdbxTransaction<'_, K, E>,
+    number: impl Into<BlockNumber>,
+) -> anyhow::Result<Option<H256>> {
+    let number = number.into();
+    trace!("Computing transactions root for block {number}");
 
-        assert_eq!(body, recovered_body);
-        assert_eq!(block1_hash, recovered_hash);
-        assert_eq!(txs, *recovered_txs);
-        assert_eq!(senders, *recovered_senders);
+    Ok(block_body::read_without_senders(tx, number)?.map(|body| body.transactions_root()))
+}
+
+/// Withdrawals trie root of the block, to be checked against `withdrawals_root` of its header.
+/// Returns `None` for pre-Shanghai blocks without withdrawals.
+pub fn compute_withdrawals_root<K: TransactionKind, E: EnvironmentKind>(
+    tx: &MdbxTransaction<'_, K, E>,
+    number: impl Into<BlockNumber>,
+) -> anyhow::Result<Option<H256>> {
+    let number = number.into();
+    trace!("Computing withdrawals root for block {number}");
+
+    Ok(withdrawals::read(tx, number)?.map(|withdrawals| crate::trie::root_hash(&withdrawals)))
+}
+
+/// Withdrawals of block `number`, `None` for pre-Shanghai blocks or if the stored header does not hash to `hash`.
+pub fn read_withdrawals<K: TransactionKind, E: EnvironmentKind>(
+    tx: &MdbxTransaction<'_, K, E>,
+    hash: H256,
+    number: impl Into<BlockNumber>,
+) -> anyhow::Result<Option<Vec<Withdrawal>>> {
+    let number = number.into();
+    trace!("Reading withdrawals for block {number}/{hash:?}");
+
+    match header::read(tx, number)? {
+        Some(header) if header.hash() == hash => withdrawals::read(tx, number),
+        _ => Ok(None),
+    }
+}
+
+/// Blocks in `[from, to]` whose transaction ids do not start right after those of the previous
+/// stored body, meaning ids are either skipped or shared with the previous block.
+/// The first body in the range is checked against the body of block `from - 1`, if there is one.
+pub fn verify_tx_id_contiguity<K: TransactionKind, E: EnvironmentKind>(
+    tx: &MdbxTransaction<'_, K, E>,
+    from: impl Into<BlockNumber>,
+    to: impl Into<BlockNumber>,
+) -> anyhow::Result<Vec<BlockNumber>> {
+    let from = from.into();
+    let to = to.into();
+    trace!("Verifying transaction id contiguity for blocks {from}..={to}");
+
+    let mut next_tx_id = if let Some(parent) = from.0.checked_sub(1) {
+        tx.get(tables::BlockBody, BlockNumber(parent))?
+            .map(|body| body.base_tx_id + body.tx_amount)
+    } else {
+        None
+    };
+
+    let mut discontinuities = vec![];
+    let mut walker = tx.cursor(tables::BlockBody)?.walk(Some(from));
+    while let Some((number, body)) = walker.next().transpose()? {
+        if number > to {
+            break;
+        }
+
+        if let Some(next_tx_id) = next_tx_id {
+            if body.base_tx_id != next_tx_id {
+                discontinuities.push(number);
+            }
+        }
+        next_tx_id = Some(body.base_tx_id + body.tx_amount);
+    }
+
+    Ok(discontinuities)
+}
+
+/// Make stored headers of blocks `from..=to` canonical again, restoring `CanonicalHeader` and
+/// `HeaderNumber` entries, e.g. for headers kept by a soft unwind. `hashes` are the expected
+/// hashes of the blocks, checked against the stored headers.
+pub fn recanonicalize<E: EnvironmentKind>(
+    tx: &MdbxTransaction<'_, RW, E>,
+    from: impl Into<BlockNumber>,
+    to: impl Into<BlockNumber>,
+    hashes: &[H256],
+) -> anyhow::Result<()> {
+    let from = from.into();
+    let to = to.into();
+    trace!("Re-canonicalizing blocks {from}..={to}");
+
+    if to < from || hashes.len() as u64 != *to - *from + 1 {
+        anyhow::bail!("{} hashes given for blocks {from}..={to}", hashes.len());
+    }
+
+    let mut canonical_cursor = tx.cursor(tables::CanonicalHeader)?;
+    let mut header_number_cursor = tx.cursor(tables::HeaderNumber)?;
+    let mut walker = tx.cursor(tables::Header)?.walk(Some(from));
+    for (number, &hash) in (from..=to).zip(hashes) {
+        match walker.next().transpose()? {
+            Some((stored_number, header)) if stored_number == number && header.hash() == hash => {}
+            _ => anyhow::bail!("no stored header #{number} with hash {hash:?}"),
+        }
+
+        canonical_cursor.upsert(number, hash)?;
+        header_number_cursor.upsert(hash, number)?;
+    }
+
+    Ok(())
+}
+
+/// Overview of a canonical block, read without decoding its transactions.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BlockSummary {
+    pub number: BlockNumber,
+    pub hash: H256,
+    pub gas_used: u64,
+    pub gas_limit: u64,
+    pub tx_count: u64,
+    /// Bytes the stored header, body and transactions take in the database, keys excluded.
+    pub size_bytes: usize,
+    pub timestamp: u64,
+}
+
+/// Summary of a canonical block, `None` if its hash, header or body is missing.
+pub fn block_summary<K: TransactionKind, E: EnvironmentKind>(
+    tx: &MdbxTransaction<'_, K, E>,
+    number: impl Into<BlockNumber>,
+) -> anyhow::Result<Option<BlockSummary>> {
+    let number = number.into();
+    trace!("Reading summary of block {number}");
+
+    let hash = if let Some(hash) = canonical_hash::read(tx, number)? {
+        hash
+    } else {
+        return Ok(None);
+    };
+    let header = tx.get(
+        tables::Header.erased(),
+        ErasedTable::<tables::Header>::encode_key(number).to_vec(),
+    )?;
+    let body = tx.get(
+        tables::BlockBody.erased(),
+        ErasedTable::<tables::BlockBody>::encode_key(number).to_vec(),
+    )?;
+    let (header_bytes, body_bytes) = if let (Some(header), Some(body)) = (header, body) {
+        (header, body)
+    } else {
+        return Ok(None);
+    };
+    let header = BlockHeader::decode(&header_bytes)?;
+    let body = BodyForStorage::decode(&body_bytes)?;
+
+    let mut size_bytes = header_bytes.len() + body_bytes.len();
+    let mut walker = tx.cursor(tables::BlockTransaction.erased())?.walk(Some(
+        ErasedTable::<tables::BlockTransaction>::encode_key(body.base_tx_id).to_vec(),
+    ));
+    for i in 0..body.tx_amount {
+        let tx_id = body.base_tx_id + i;
+        let key = ErasedTable::<tables::BlockTransaction>::encode_key(tx_id);
+        let (_, transaction) = walker
+            .next()
+            .transpose()?
+            .filter(|(k, _)| k[..] == key[..])
+            .ok_or_else(|| format_err!("missing transaction {tx_id} of block #{number}"))?;
+        size_bytes += transaction.len();
+    }
+
+    Ok(Some(BlockSummary {
+        number,
+        hash,
+        gas_used: header.gas_used,
+        gas_limit: header.gas_limit,
+        tx_count: body.tx_amount,
+        size_bytes,
+        timestamp: header.timestamp,
+    }))
+}
+
+/// Build `HeaderNumber` index for canonical blocks in `[from, to]` range.
+pub fn build_header_number_index<E: EnvironmentKind>(
+    tx: &MdbxTransaction<'_, RW, E>,
+    from: impl Into<BlockNumber>,
+    to: impl Into<BlockNumber>,
+) -> anyhow::Result<()> {
+    let from = from.into();
+    let to = to.into();
+    trace!("Building header number index for blocks {from}..={to}");
+
+    let mut header_number_cursor = tx.cursor(tables::HeaderNumber)?;
+    let mut walker = tx.cursor(tables::CanonicalHeader)?.walk(Some(from));
+    while let Some((block_number, hash)) = walker.next().transpose()? {
+        if block_number > to {
+            break;
+        }
+
+        header_number_cursor.upsert(hash, block_number)?;
+    }
+
+    Ok(())
+}
+
+/// Compute rewards for each ommer included in the block.
+///
+/// Base block reward depends on the active fork and is taken from `block_reward` schedule:
+/// 5 ETH since Frontier, 3 ETH since Byzantium (EIP-649) and 2 ETH since Constantinople (EIP-1234).
+/// Ommer receives `(8 + ommer_number - block_number) / 8` of the base reward.
+pub fn uncle_rewards<K: TransactionKind, E: EnvironmentKind>(
+    tx: &MdbxTransaction<'_, K, E>,
+    number: impl Into<BlockNumber>,
+    block_reward: &BlockRewardSchedule,
+) -> anyhow::Result<Vec<(H256, U256)>> {
+    let number = number.into();
+    trace!("Computing uncle rewards for block {number}");
+
+    let body = storage_body::read(tx, number)?
+        .ok_or_else(|| format_err!("no body for block #{number}"))?;
+    let base_reward = block_reward.for_block(number);
+
+    body.ommers
+        .iter()
+        .map(|ommer| {
+            let distance = number
+                .0
+                .checked_sub(ommer.number.0)
+                .filter(|&distance| distance > 0 && distance < 8)
+                .ok_or_else(|| {
+                    format_err!(
+                        "ommer #{} is not a valid ommer of block #{number}",
+                        ommer.number
+                    )
+                })?;
+
+            Ok((ommer.hash(), (U256::from(8 - distance) * base_reward) >> 3))
+        })
+        .collect()
+}
+
+/// Find the highest block of `chain` that is also part of the canonical chain in the database.
+///
+/// `chain` must be sorted by block number in ascending order. Parent of the lowest header is also considered.
+pub fn find_common_ancestor<K: TransactionKind, E: EnvironmentKind>(
+    tx: &MdbxTransaction<'_, K, E>,
+    chain: &[(H256, BlockHeader)],
+) -> anyhow::Result<Option<(BlockNumber, H256)>> {
+    trace!(
+        "Finding common ancestor for chain of {} headers",
+        chain.len()
+    );
+
+    let mut ancestor = None;
+
+    if let Some((_, lowest)) = chain.first() {
+        if let Some(parent_number) = lowest.number.0.checked_sub(1).map(BlockNumber) {
+            if canonical_hash::read(tx, parent_number)? == Some(lowest.parent_hash) {
+                ancestor = Some((parent_number, lowest.parent_hash));
+            }
+        }
+    }
+
+    for (hash, header) in chain {
+        match canonical_hash::read(tx, header.number)? {
+            Some(canonical_hash) if canonical_hash == *hash => {
+                ancestor = Some((header.number, *hash));
+            }
+            _ => break,
+        }
+    }
+
+    Ok(ancestor)
+}
+
+/// Rewrite canonical hashes of blocks in `(from, to]` to match the chain formed by parent hashes
+/// of stored headers, walking down from the header at `to`.
+///
+/// Canonical block `from` is the trusted anchor: the walk must end at it, otherwise nothing is written.
+/// Returns the number of rewritten entries.
+pub fn repair_canonical_from_parents<E: EnvironmentKind>(
+    tx: &MdbxTransaction<'_, RW, E>,
+    from: impl Into<BlockNumber>,
+    to: impl Into<BlockNumber>,
+) -> anyhow::Result<usize> {
+    let from = from.into();
+    let to = to.into();
+    trace!("Repairing canonical chain for blocks {from}..={to}");
+
+    if !header::stored_header_hash(tx, from)? {
+        anyhow::bail!("trusted anchor #{from} has no canonical header matching its hash");
+    }
+    let anchor = canonical_hash::read(tx, from)?
+        .ok_or_else(|| format_err!("no canonical hash for trusted anchor #{from}"))?;
+
+    let mut chain = Vec::new();
+    let mut expected_hash = None;
+    for number in (from + 1_u8..=to).rev() {
+        let header = header::read(tx, number)?
+            .ok_or_else(|| format_err!("no header for block #{number}"))?;
+        let hash = header.hash();
+        if let Some(expected_hash) = expected_hash {
+            anyhow::ensure!(
+                hash == expected_hash,
+                "header #{number} is not the parent of header #{}",
+                number + 1_u8
+            );
+        }
+        expected_hash = Some(header.parent_hash);
+        chain.push((number, hash));
+    }
+
+    if let Some(expected_hash) = expected_hash {
+        anyhow::ensure!(
+            expected_hash == anchor,
+            "chain at #{to} does not descend from trusted anchor #{from}"
+        );
+    }
+
+    let mut changed = 0;
+    for (number, hash) in chain.into_iter().rev() {
+        let canonical_hash = canonical_hash::read(tx, number)?;
+        if canonical_hash != Some(hash) {
+            if let Some(canonical_hash) = canonical_hash {
+                tx.del(tables::HeaderNumber, canonical_hash, None)?;
+            }
+            tx.set(tables::CanonicalHeader, number, hash)?;
+            tx.set(tables::HeaderNumber, hash, number)?;
+            changed += 1;
+        }
+    }
+
+    Ok(changed)
+}
+
+/// Latest `count` canonical blocks with bodies, newest first, read in a single backward pass over each table.
+///
+/// Fewer blocks are returned if the chain is shorter, or if it has a gap in headers or bodies.
+/// Headers above the last body, e.g. while the bodies stage catches up, are skipped.
+pub fn read_recent_blocks<K: TransactionKind, E: EnvironmentKind>(
+    tx: &MdbxTransaction<'_, K, E>,
+    count: usize,
+) -> anyhow::Result<Vec<Block>> {
+    trace!("Reading {count} recent blocks");
+
+    let mut canonical_cursor = tx.cursor(tables::CanonicalHeader)?;
+    let mut header_cursor = tx.cursor(tables::Header)?;
+    let mut body_cursor = tx.cursor(tables::BlockBody)?;
+    let mut tx_cursor = tx.cursor(tables::BlockTransaction)?;
+
+    let mut out = vec![];
+    let last_canonical = canonical_cursor.last()?.map(|(number, _)| number);
+    let last_body = body_cursor.last()?.map(|(number, _)| number);
+    let mut canonical = match (last_canonical, last_body) {
+        (Some(last_canonical), Some(last_body)) => {
+            canonical_cursor.seek_exact(std::cmp::min(last_canonical, last_body))?
+        }
+        _ => None,
+    };
+    while out.len() < count {
+        let number = match canonical {
+            Some((number, _)) => number,
+            None => break,
+        };
+
+        let (header, body) = if out.is_empty() {
+            (
+                header_cursor.seek_exact(number)?,
+                body_cursor.seek_exact(number)?,
+            )
+        } else {
+            (header_cursor.prev()?, body_cursor.prev()?)
+        };
+        let (header, body) = match (header, body) {
+            (Some((header_number, header)), Some((body_number, body)))
+                if header_number == number && body_number == number =>
+            {
+                (header, body)
+            }
+            _ => break,
+        };
+
+        let mut transactions = Vec::with_capacity(body.tx_amount.try_into()?);
+        if body.tx_amount > 0 {
+            let mut entry = tx_cursor.seek_exact(body.base_tx_id)?;
+            for _ in 0..body.tx_amount {
+                let (_, transaction) =
+                    entry.ok_or_else(|| format_err!("missing transactions for block #{number}"))?;
+                transactions.push(transaction);
+                entry = tx_cursor.next()?;
+            }
+        }
+
+        out.push(Block {
+            header,
+            transactions,
+            ommers: body.ommers,
+        });
+
+        canonical = canonical_cursor.prev()?;
+    }
+
+    Ok(out)
+}
+
+/// Digest of the canonical chain over blocks `from..=to`, for comparing chains between nodes
+/// without transferring them.
+///
+/// The digest is keccak256 over the concatenation, for each block in ascending order, of its canonical hash
+/// followed, if `include_bodies` is set, by keccak256 of its RLP encoded body (transactions, then ommers).
+/// Block numbers themselves are not hashed. Fails if any block in the range is missing.
+pub fn range_checksum<K: TransactionKind, E: EnvironmentKind>(
+    tx: &MdbxTransaction<'_, K, E>,
+    from: impl Into<BlockNumber>,
+    to: impl Into<BlockNumber>,
+    include_bodies: bool,
+) -> anyhow::Result<H256> {
+    let from = from.into();
+    let to = to.into();
+    trace!("Computing checksum for blocks {from}..={to}");
+
+    let mut hasher = Keccak256::new();
+    for number in from..=to {
+        let hash = canonical_hash::read(tx, number)?
+            .ok_or_else(|| format_err!("no canonical hash for block #{number}"))?;
+        hasher.update(hash.as_bytes());
+
+        if include_bodies {
+            let body = block_body::read_without_senders(tx, number)?
+                .ok_or_else(|| format_err!("no body for block #{number}"))?;
+            let mut out = BytesMut::new();
+            body.encode(&mut out);
+            hasher.update(keccak256(&out).as_bytes());
+        }
+    }
+
+    Ok(H256::from_slice(&hasher.finalize()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv::{new_mem_chaindata, traits::TableEncode};
+    use bytes::Bytes;
+
+    fn legacy_tx(nonce: u64) -> MessageWithSignature {
+        MessageWithSignature {
+            message: Message::Legacy {
+                chain_id: None,
+                nonce,
+                gas_price: 1.as_u256(),
+                gas_limit: 21_000,
+                action: TransactionAction::Create,
+                value: 0.as_u256(),
+                input: Bytes::new(),
+            },
+            signature: MessageSignature::new(false, H256::repeat_byte(1), H256::repeat_byte(1))
+                .unwrap(),
+        }
+    }
+
+    fn write_body_with_txs<E: EnvironmentKind>(
+        rwtx: &MdbxTransaction<'_, RW, E>,
+        number: impl Into<BlockNumber>,
+        base_tx_id: u64,
+        txs: &[MessageWithSignature],
+    ) {
+        storage_body::write(
+            rwtx,
+            number,
+            &BodyForStorage {
+                base_tx_id: TxIndex(base_tx_id),
+                tx_amount: txs.len() as u64,
+                ommers: Default::default(),
+                withdrawals: None,
+            },
+        )
+        .unwrap();
+        tx::write(rwtx, base_tx_id, txs).unwrap();
+    }
+
+    #[test]
+    fn accessors() {
+        let tx1 = MessageWithSignature {
+            message: Message::Legacy {
+                chain_id: None,
+                nonce: 1,
+                gas_price: 20_000.as_u256(),
+                gas_limit: 3_000_000,
+                action: TransactionAction::Create,
+                value: 0.as_u256(),
+                input: Bytes::new(),
+            },
+            signature: MessageSignature::new(false, H256::repeat_byte(2), H256::repeat_byte(3))
+                .unwrap(),
+        };
+        let tx2 = MessageWithSignature {
+            message: Message::Legacy {
+                chain_id: None,
+                nonce: 2,
+                gas_price: 30_000.as_u256(),
+                gas_limit: 1_000_000,
+                action: TransactionAction::Create,
+                value: 10.as_u256(),
+                input: Bytes::new(),
+            },
+            signature: MessageSignature::new(true, H256::repeat_byte(6), H256::repeat_byte(9))
+                .unwrap(),
+        };
+        let txs = [tx1, tx2];
+
+        let sender1 = Address::random();
+        let sender2 = Address::random();
+        let senders = [sender1, sender2];
+
+        let block1_hash = H256::random();
+        let body = BodyForStorage {
+            base_tx_id: 1.into(),
+            tx_amount: 2,
+            ommers: Default::default(),
+            withdrawals: None,
+        };
+
+        let db = new_mem_chaindata().unwrap();
+        let rwtx = db.begin_mutable().unwrap();
+        let rwtx = &rwtx;
+
+        storage_body::write(rwtx, 1, &body).unwrap();
+        rwtx.set(tables::CanonicalHeader, 1.into(), block1_hash)
+            .unwrap();
+        tx::write(rwtx, 1, &txs).unwrap();
+        tx_sender::write(rwtx, 1, senders.to_vec()).unwrap();
+
+        let recovered_body = storage_body::read(rwtx, 1)
+            .unwrap()
+            .expect("Could not recover storage body.");
+        let recovered_hash = rwtx
+            .get(tables::CanonicalHeader, 1.into())
+            .unwrap()
+            .expect("Could not recover block hash");
+        let recovered_txs = tx::read(rwtx, 1, 2).unwrap();
+        let recovered_senders = tx_sender::read(rwtx, 1).unwrap();
+
+        assert_eq!(body, recovered_body);
+        assert_eq!(block1_hash, recovered_hash);
+        assert_eq!(txs, *recovered_txs);
+        assert_eq!(senders, *recovered_senders);
+
+        let header = BlockHeader {
+            number: 1.into(),
+            ..Default::default()
+        };
+        rwtx.set(tables::Header, 1.into(), header.clone()).unwrap();
+        let block = block::read_with_senders(rwtx, header.hash(), 1)
+            .unwrap()
+            .expect("Could not recover block");
+        assert_eq!(block.header, header);
+        assert_eq!(
+            block
+                .transactions
+                .iter()
+                .map(|tx| (tx.message.clone(), tx.sender))
+                .collect::<Vec<_>>(),
+            txs.iter()
+                .map(|tx| tx.message.clone())
+                .zip(senders)
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(
+            block::read_with_senders(rwtx, block1_hash, 1).unwrap(),
+            None
+        );
+        assert_eq!(
+            block::read_with_senders(rwtx, header.hash(), 2).unwrap(),
+            None
+        );
+
+        let header2 = BlockHeader {
+            number: 2.into(),
+            ..Default::default()
+        };
+        rwtx.set(tables::Header, 2.into(), header2.clone()).unwrap();
+        assert!(block::read_with_senders(rwtx, header2.hash(), 2).is_err());
+
+        for _ in 0..2 {
+            tx::delete_range(rwtx, 1, 2).unwrap();
+            tx_sender::delete_range(rwtx, 1, 1).unwrap();
+
+            assert_eq!(tx::read(rwtx, 1, 2).unwrap(), vec![]);
+            assert_eq!(tx_sender::read(rwtx, 1).unwrap(), vec![]);
+        }
+    }
+
+    #[test]
+    fn stream_transactions() {
+        let db = new_mem_chaindata().unwrap();
+        let rwtx = db.begin_mutable().unwrap();
+        let rwtx = &rwtx;
+
+        // Block 2 has no body
+        write_body_with_txs(rwtx, 1, 1, &[legacy_tx(1), legacy_tx(2)]);
+        write_body_with_txs(rwtx, 3, 3, &[]);
+        write_body_with_txs(rwtx, 4, 3, &[legacy_tx(3)]);
+
+        assert_eq!(
+            tx::stream_range(rwtx, 1, 4, true)
+                .map(|res| res.map(|(number, tx_id, eth_tx)| (number.0, tx_id.0, eth_tx)))
+                .collect::<anyhow::Result<Vec<_>>>()
+                .unwrap(),
+            vec![
+                (1, 1, legacy_tx(1)),
+                (1, 2, legacy_tx(2)),
+                (4, 3, legacy_tx(3))
+            ]
+        );
+        assert!(tx::stream_range(rwtx, 1, 4, false)
+            .collect::<anyhow::Result<Vec<_>>>()
+            .is_err());
+        assert_eq!(tx::stream_range(rwtx, 3, 3, false).count(), 0);
+    }
+
+    #[test]
+    fn header_number_index() {
+        let db = new_mem_chaindata().unwrap();
+        let rwtx = db.begin_mutable().unwrap();
+        let rwtx = &rwtx;
+
+        let hashes = (0..5).map(|_| H256::random()).collect::<Vec<_>>();
+        for (number, hash) in hashes.iter().enumerate() {
+            rwtx.set(tables::CanonicalHeader, (number as u64).into(), *hash)
+                .unwrap();
+        }
+
+        build_header_number_index(rwtx, 1, 3).unwrap();
+
+        for (number, hash) in hashes.iter().enumerate() {
+            assert_eq!(
+                header_number::read(rwtx, *hash).unwrap(),
+                (1..=3)
+                    .contains(&number)
+                    .then_some(BlockNumber(number as u64))
+            );
+        }
+    }
+
+    #[test]
+    fn body_withdrawals() {
+        let db = new_mem_chaindata().unwrap();
+        let rwtx = db.begin_mutable().unwrap();
+        let rwtx = &rwtx;
+
+        let pre_shanghai = BodyForStorage {
+            base_tx_id: 1.into(),
+            tx_amount: 2,
+            ommers: Default::default(),
+            withdrawals: None,
+        };
+        let post_shanghai = BodyForStorage {
+            base_tx_id: 3.into(),
+            tx_amount: 0,
+            ommers: Default::default(),
+            withdrawals: Some(vec![
+                Withdrawal {
+                    index: 0,
+                    validator_index: 5,
+                    address: Address::from_low_u64_be(0xa),
+                    amount: 32_000_000_000,
+                },
+                Withdrawal {
+                    index: 1,
+                    validator_index: 6,
+                    address: Address::from_low_u64_be(0xb),
+                    amount: 1,
+                },
+            ]),
+        };
+        let empty_withdrawals = BodyForStorage {
+            base_tx_id: 3.into(),
+            tx_amount: 0,
+            ommers: Default::default(),
+            withdrawals: Some(vec![]),
+        };
+
+        storage_body::write(rwtx, 1, &pre_shanghai).unwrap();
+        storage_body::write(rwtx, 2, &post_shanghai).unwrap();
+        storage_body::write(rwtx, 3, &empty_withdrawals).unwrap();
+
+        assert_eq!(storage_body::read(rwtx, 1).unwrap(), Some(pre_shanghai));
+        assert_eq!(withdrawals::read(rwtx, 1).unwrap(), None);
+        assert_eq!(
+            storage_body::read(rwtx, 2).unwrap(),
+            Some(post_shanghai.clone())
+        );
+        assert_eq!(
+            withdrawals::read(rwtx, 2).unwrap(),
+            post_shanghai.withdrawals
+        );
+
+        // Lookup by hash only finds withdrawals of the stored header
+        let header = BlockHeader {
+            number: BlockNumber(2),
+            ..Default::default()
+        };
+        let hash = header.hash();
+        rwtx.set(tables::Header, BlockNumber(2), header).unwrap();
+        assert_eq!(
+            super::read_withdrawals(rwtx, hash, 2).unwrap(),
+            post_shanghai.withdrawals
+        );
+        assert_eq!(
+            super::read_withdrawals(rwtx, H256::repeat_byte(1), 2).unwrap(),
+            None
+        );
+        assert_eq!(super::read_withdrawals(rwtx, hash, 3).unwrap(), None);
+
+        assert_eq!(
+            storage_body::read(rwtx, 3).unwrap(),
+            Some(empty_withdrawals)
+        );
+
+        // Rewriting the body without withdrawals drops them
+        storage_body::write(
+            rwtx,
+            2,
+            &BodyForStorage {
+                withdrawals: None,
+                ..post_shanghai
+            },
+        )
+        .unwrap();
+        assert_eq!(withdrawals::read(rwtx, 2).unwrap(), None);
+    }
+
+    #[test]
+    fn td_monotonic() {
+        let db = new_mem_chaindata().unwrap();
+        let rwtx = db.begin_mutable().unwrap();
+        let rwtx = &rwtx;
+
+        for (number, difficulty, td) in [(0, 1, 1), (1, 2, 3), (2, 3, 6), (3, 0, 6), (4, 1, 6)] {
+            rwtx.set(
+                tables::Header,
+                BlockNumber(number),
+                BlockHeader {
+                    number: BlockNumber(number),
+                    difficulty: difficulty.as_u256(),
+                    ..Default::default()
+                },
+            )
+            .unwrap();
+            rwtx.set(
+                tables::HeadersTotalDifficulty,
+                BlockNumber(number),
+                td.as_u256(),
+            )
+            .unwrap();
+        }
+
+        assert_eq!(td::assert_monotonic(rwtx, 0, 2).unwrap(), None);
+        // Zero difficulty block keeps parent's total
+        assert_eq!(td::assert_monotonic(rwtx, 0, 3).unwrap(), None);
+        assert_eq!(
+            td::assert_monotonic(rwtx, 0, 4).unwrap(),
+            Some(BlockNumber(4))
+        );
+
+        rwtx.set(tables::HeadersTotalDifficulty, BlockNumber(2), 2.as_u256())
+            .unwrap();
+        assert_eq!(
+            td::assert_monotonic(rwtx, 0, 4).unwrap(),
+            Some(BlockNumber(2))
+        );
+        assert!(td::assert_monotonic(rwtx, 3, 5).is_err());
+    }
+
+    #[test]
+    fn td_read_range() {
+        let db = new_mem_chaindata().unwrap();
+        let rwtx = db.begin_mutable().unwrap();
+        let rwtx = &rwtx;
+
+        for number in 0..5 {
+            rwtx.set(tables::CanonicalHeader, BlockNumber(number), H256::zero())
+                .unwrap();
+            if number != 3 {
+                rwtx.set(
+                    tables::HeadersTotalDifficulty,
+                    BlockNumber(number),
+                    (number + 1).as_u256(),
+                )
+                .unwrap();
+            }
+        }
+
+        assert_eq!(
+            td::read_range(rwtx, 0, 2).unwrap(),
+            vec![
+                (BlockNumber(0), 1.as_u256()),
+                (BlockNumber(1), 2.as_u256()),
+                (BlockNumber(2), 3.as_u256()),
+            ]
+        );
+        assert!(td::read_range(rwtx, 1, 4).is_err());
+        // Above the canonical chain
+        assert_eq!(
+            td::read_range(rwtx, 4, 10).unwrap(),
+            vec![(BlockNumber(4), 5.as_u256())]
+        );
+        assert_eq!(td::read_range(rwtx, 5, 10).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn write_bounded_body() {
+        let db = new_mem_chaindata().unwrap();
+        let rwtx = db.begin_mutable().unwrap();
+        let rwtx = &rwtx;
+
+        // 16 transactions of 77 bytes each
+        let body = BlockBody {
+            transactions: (0..16).map(legacy_tx).collect(),
+            ommers: Default::default(),
+        };
+        let body_withdrawals = vec![Withdrawal {
+            index: 0,
+            validator_index: 1,
+            address: Address::from_low_u64_be(0xa),
+            amount: 1,
+        }];
+
+        assert!(storage_body::write_bounded(
+            rwtx,
+            H256::zero(),
+            1,
+            0,
+            &body,
+            Some(body_withdrawals.clone()),
+            1024
+        )
+        .is_err());
+        assert_eq!(storage_body::read(rwtx, 1).unwrap(), None);
+
+        storage_body::write_bounded(
+            rwtx,
+            H256::zero(),
+            1,
+            0,
+            &body,
+            Some(body_withdrawals.clone()),
+            2048,
+        )
+        .unwrap();
+        assert_eq!(
+            block_body::read_without_senders(rwtx, 1).unwrap(),
+            Some(body.clone())
+        );
+        assert_eq!(withdrawals::read(rwtx, 1).unwrap(), Some(body_withdrawals));
+
+        // Rewriting the body without withdrawals drops them
+        storage_body::write_bounded(rwtx, H256::zero(), 1, 0, &body, None, 2048).unwrap();
+        assert_eq!(withdrawals::read(rwtx, 1).unwrap(), None);
+    }
+
+    #[test]
+    fn repair_canonical() {
+        let db = new_mem_chaindata().unwrap();
+        let rwtx = db.begin_mutable().unwrap();
+        let rwtx = &rwtx;
+
+        let mut parent_hash = H256::zero();
+        let mut hashes = vec![];
+        for number in (0..5).map(BlockNumber) {
+            let header = BlockHeader {
+                number,
+                parent_hash,
+                ..Default::default()
+            };
+            parent_hash = header.hash();
+            hashes.push(parent_hash);
+            rwtx.set(tables::CanonicalHeader, number, parent_hash)
+                .unwrap();
+            rwtx.set(tables::Header, number, header).unwrap();
+        }
+
+        assert_eq!(repair_canonical_from_parents(rwtx, 0, 4).unwrap(), 0);
+
+        // Corrupt canonical index
+        for number in [2, 3] {
+            rwtx.set(tables::CanonicalHeader, number.into(), H256::random())
+                .unwrap();
+        }
+        assert_eq!(repair_canonical_from_parents(rwtx, 1, 4).unwrap(), 2);
+        for (number, hash) in hashes.iter().enumerate() {
+            let number = BlockNumber(number as u64);
+            assert_eq!(canonical_hash::read(rwtx, number).unwrap(), Some(*hash));
+            assert_eq!(header_number::read(rwtx, *hash).unwrap(), Some(number));
+        }
+
+        // Anchor is not trusted
+        rwtx.set(tables::CanonicalHeader, 1.into(), H256::random())
+            .unwrap();
+        assert!(repair_canonical_from_parents(rwtx, 1, 4).is_err());
+
+        // Parent links are broken, nothing is rewritten
+        rwtx.set(tables::CanonicalHeader, 1.into(), hashes[1])
+            .unwrap();
+        rwtx.set(
+            tables::Header,
+            2.into(),
+            BlockHeader {
+                number: 2.into(),
+                parent_hash: H256::random(),
+                ..Default::default()
+            },
+        )
+        .unwrap();
+        rwtx.set(tables::CanonicalHeader, 3.into(), H256::random())
+            .unwrap();
+        assert!(repair_canonical_from_parents(rwtx, 1, 4).is_err());
+        assert_ne!(canonical_hash::read(rwtx, 3).unwrap(), Some(hashes[3]));
+    }
+
+    #[test]
+    fn read_many_canonical_hashes() {
+        let db = new_mem_chaindata().unwrap();
+        let rwtx = db.begin_mutable().unwrap();
+        let rwtx = &rwtx;
+
+        for number in [0, 1, 2, 5, 6] {
+            rwtx.set(
+                tables::CanonicalHeader,
+                BlockNumber(number),
+                H256::from_low_u64_be(number + 1),
+            )
+            .unwrap();
+        }
+
+        let numbers = [6, 0, 3, 5, 5, 100, 1]
+            .into_iter()
+            .map(BlockNumber)
+            .collect::<Vec<_>>();
+        let hashes = canonical_hash::read_many(rwtx, &numbers).unwrap();
+        assert_eq!(
+            hashes,
+            numbers
+                .iter()
+                .map(|&number| canonical_hash::read(rwtx, number).unwrap())
+                .collect::<Vec<_>>()
+        );
+        assert_eq!(hashes[2], None);
+        assert_eq!(hashes[3], Some(H256::from_low_u64_be(6)));
+
+        assert_eq!(canonical_hash::read_many(rwtx, &[]).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn canonical_tip() {
+        let db = new_mem_chaindata().unwrap();
+        let rwtx = db.begin_mutable().unwrap();
+        let rwtx = &rwtx;
+
+        assert_eq!(super::canonical_tip(rwtx).unwrap(), None);
+
+        for number in 0..3 {
+            rwtx.set(
+                tables::CanonicalHeader,
+                BlockNumber(number),
+                H256::from_low_u64_be(number),
+            )
+            .unwrap();
+        }
+        assert_eq!(
+            super::canonical_tip(rwtx).unwrap(),
+            Some(BlockHashAndNumber {
+                hash: H256::from_low_u64_be(2),
+                number: BlockNumber(2),
+            })
+        );
+    }
+
+    #[test]
+    fn find_block_of_tx() {
+        let db = new_mem_chaindata().unwrap();
+        let rwtx = db.begin_mutable().unwrap();
+        let rwtx = &rwtx;
+
+        let txs = (0..3).map(legacy_tx).collect::<Vec<_>>();
+        let block_hash = H256::repeat_byte(0xbb);
+
+        rwtx.set(tables::CanonicalHeader, BlockNumber(1), block_hash)
+            .unwrap();
+        write_body_with_txs(rwtx, 1, 5, &txs[..2]);
+        for t in &txs[..2] {
+            tl::write(rwtx, t.hash(), BlockNumber(1)).unwrap();
+        }
+
+        assert_eq!(
+            super::find_block_of_tx(rwtx, txs[1].hash()).unwrap(),
+            Some((BlockNumber(1), block_hash, 1))
+        );
+        assert_eq!(super::find_block_of_tx(rwtx, txs[2].hash()).unwrap(), None);
+
+        // Lookup points to a block without body
+        tl::write(rwtx, txs[2].hash(), BlockNumber(2)).unwrap();
+        rwtx.set(tables::CanonicalHeader, BlockNumber(2), H256::zero())
+            .unwrap();
+        assert!(super::find_block_of_tx(rwtx, txs[2].hash()).is_err());
+    }
+
+    #[test]
+    fn recanonicalize() {
+        let db = new_mem_chaindata().unwrap();
+        let rwtx = db.begin_mutable().unwrap();
+        let rwtx = &rwtx;
+
+        let headers = (1..=3)
+            .map(|number| BlockHeader {
+                number: BlockNumber(number),
+                ..Default::default()
+            })
+            .collect::<Vec<_>>();
+        for header in &headers {
+            rwtx.set(tables::Header, header.number, header.clone())
+                .unwrap();
+        }
+        let hashes = headers.iter().map(|h| h.hash()).collect::<Vec<_>>();
+
+        assert!(super::recanonicalize(rwtx, 1, 2, &hashes).is_err());
+        assert!(super::recanonicalize(rwtx, 2, 3, &[hashes[0], hashes[2]]).is_err());
+        assert_eq!(canonical_hash::read(rwtx, 2).unwrap(), None);
+
+        super::recanonicalize(rwtx, 1, 3, &hashes).unwrap();
+        for (header, hash) in headers.iter().zip(&hashes) {
+            assert_eq!(
+                canonical_hash::read(rwtx, header.number).unwrap(),
+                Some(*hash)
+            );
+            assert_eq!(
+                header_number::read(rwtx, *hash).unwrap(),
+                Some(header.number)
+            );
+        }
+        assert!(super::recanonicalize(rwtx, 3, 4, &hashes[1..]).is_err());
+    }
+
+    #[test]
+    fn block_summary() {
+        let db = new_mem_chaindata().unwrap();
+        let rwtx = db.begin_mutable().unwrap();
+        let rwtx = &rwtx;
+
+        let txs = (0..2).map(legacy_tx).collect::<Vec<_>>();
+        let header = BlockHeader {
+            number: BlockNumber(1),
+            gas_limit: 30_000_000,
+            gas_used: 42_000,
+            timestamp: 1_000,
+            ..Default::default()
+        };
+        let body = BodyForStorage {
+            base_tx_id: 3.into(),
+            tx_amount: 2,
+            ommers: Default::default(),
+            withdrawals: None,
+        };
+
+        rwtx.set(tables::CanonicalHeader, BlockNumber(1), header.hash())
+            .unwrap();
+        rwtx.set(tables::Header, BlockNumber(1), header.clone())
+            .unwrap();
+        assert_eq!(super::block_summary(rwtx, 1).unwrap(), None);
+
+        storage_body::write(rwtx, 1, &body).unwrap();
+        tx::write(rwtx, 3, &txs).unwrap();
+
+        let expected_size = TableEncode::encode(header.clone()).len()
+            + TableEncode::encode(body.clone()).len()
+            + txs
+                .iter()
+                .map(|t| TableEncode::encode(t.clone()).len())
+                .sum::<usize>();
+        assert_eq!(
+            super::block_summary(rwtx, 1).unwrap(),
+            Some(BlockSummary {
+                number: BlockNumber(1),
+                hash: header.hash(),
+                gas_used: 42_000,
+                gas_limit: 30_000_000,
+                tx_count: 2,
+                size_bytes: expected_size,
+                timestamp: 1_000,
+            })
+        );
+        assert_eq!(super::block_summary(rwtx, 2).unwrap(), None);
+    }
+
+    #[test]
+    fn tx_lookup() {
+        let db = new_mem_chaindata().unwrap();
+        let rwtx = db.begin_mutable().unwrap();
+        let rwtx = &rwtx;
+
+        let txs = (0..3).map(legacy_tx).collect::<Vec<_>>();
+        for (number, base_tx_id, txs) in [(1, 0, &txs[..2]), (2, 2, &txs[2..])] {
+            write_body_with_txs(rwtx, number, base_tx_id, txs);
+            for t in txs {
+                tl::write(rwtx, t.hash(), BlockNumber(number)).unwrap();
+            }
+        }
+
+        assert_eq!(tl::read(rwtx, txs[2].hash()).unwrap(), Some(BlockNumber(2)));
+        assert_eq!(
+            tl::walk_range(rwtx, 2, 5)
+                .collect::<anyhow::Result<Vec<_>>>()
+                .unwrap(),
+            vec![(txs[2].hash(), BlockNumber(2))]
+        );
+
+        for res in tl::walk_range(rwtx, 1, 2).collect::<Vec<_>>() {
+            tl::delete(rwtx, res.unwrap().0).unwrap();
+        }
+        for t in &txs {
+            assert_eq!(tl::read(rwtx, t.hash()).unwrap(), None);
+        }
+        assert_eq!(tl::walk_range(rwtx, 1, 2).count(), 0);
+    }
+
+    #[test]
+    fn compute_transactions_root() {
+        let db = new_mem_chaindata().unwrap();
+        let rwtx = db.begin_mutable().unwrap();
+        let rwtx = &rwtx;
+
+        let txs = vec![
+            legacy_tx(1),
+            MessageWithSignature {
+                message: Message::EIP1559 {
+                    chain_id: ChainId(1),
+                    nonce: 2,
+                    max_priority_fee_per_gas: 1.as_u256(),
+                    max_fee_per_gas: 2.as_u256(),
+                    gas_limit: 21_000,
+                    action: TransactionAction::Create,
+                    value: 0.as_u256(),
+                    input: Bytes::new(),
+                    access_list: vec![],
+                },
+                signature: MessageSignature::new(true, H256::repeat_byte(2), H256::repeat_byte(2))
+                    .unwrap(),
+            },
+        ];
+
+        write_body_with_txs(rwtx, 1, 0, &[]);
+        write_body_with_txs(rwtx, 2, 0, &txs);
+
+        assert_eq!(
+            super::compute_transactions_root(rwtx, 1).unwrap(),
+            Some(EMPTY_ROOT)
+        );
+        assert_eq!(
+            super::compute_transactions_root(rwtx, 2).unwrap(),
+            Some(
+                BlockBody {
+                    transactions: txs,
+                    ommers: Default::default(),
+                }
+                .transactions_root()
+            )
+        );
+        assert_eq!(super::compute_transactions_root(rwtx, 3).unwrap(), None);
+    }
+
+    #[test]
+    fn compute_withdrawals_root() {
+        let db = new_mem_chaindata().unwrap();
+        let rwtx = db.begin_mutable().unwrap();
+        let rwtx = &rwtx;
+
+        let withdrawals = (0..3)
+            .map(|index| Withdrawal {
+                index,
+                validator_index: 100 + index,
+                address: Address::repeat_byte(index as u8),
+                amount: 32_000_000_000,
+            })
+            .collect::<Vec<_>>();
+
+        for (number, withdrawals) in [(1, None), (2, Some(vec![])), (3, Some(withdrawals.clone()))]
+        {
+            storage_body::write(
+                rwtx,
+                number,
+                &BodyForStorage {
+                    base_tx_id: TxIndex(0),
+                    tx_amount: 0,
+                    ommers: Default::default(),
+                    withdrawals,
+                },
+            )
+            .unwrap();
+        }
+
+        assert_eq!(super::compute_withdrawals_root(rwtx, 1).unwrap(), None);
+        assert_eq!(
+            super::compute_withdrawals_root(rwtx, 2).unwrap(),
+            Some(EMPTY_ROOT)
+        );
+        let root = super::compute_withdrawals_root(rwtx, 3).unwrap().unwrap();
+        assert_eq!(root, crate::trie::root_hash(&withdrawals));
+        assert_ne!(root, crate::trie::root_hash(&withdrawals[..2]));
+    }
+
+    #[test]
+    fn verify_tx_id_contiguity() {
+        let db = new_mem_chaindata().unwrap();
+        let rwtx = db.begin_mutable().unwrap();
+        let rwtx = &rwtx;
+
+        // Block 3 overlaps block 2, block 5 leaves a gap after block 4
+        for (number, base_tx_id, tx_amount) in [
+            (0, 0, 0),
+            (1, 0, 2),
+            (2, 2, 3),
+            (3, 4, 1),
+            (4, 5, 0),
+            (5, 7, 1),
+        ] {
+            storage_body::write(
+                rwtx,
+                number,
+                &BodyForStorage {
+                    base_tx_id: TxIndex(base_tx_id),
+                    tx_amount,
+                    ommers: Default::default(),
+                    withdrawals: None,
+                },
+            )
+            .unwrap();
+        }
+
+        assert_eq!(
+            super::verify_tx_id_contiguity(rwtx, 0, 5).unwrap(),
+            vec![BlockNumber(3), BlockNumber(5)]
+        );
+        assert_eq!(super::verify_tx_id_contiguity(rwtx, 0, 2).unwrap(), vec![]);
+        assert_eq!(
+            super::verify_tx_id_contiguity(rwtx, 3, 4).unwrap(),
+            vec![BlockNumber(3)]
+        );
+        assert_eq!(super::verify_tx_id_contiguity(rwtx, 4, 4).unwrap(), vec![]);
+    }
+
+    #[test]
+    fn write_transactions_checked() {
+        let db = new_mem_chaindata().unwrap();
+        let rwtx = db.begin_mutable().unwrap();
+        let rwtx = &rwtx;
+
+        let txs = (0..2).map(legacy_tx).collect::<Vec<_>>();
+
+        assert_eq!(storage_body::next_base_tx_id(rwtx).unwrap(), TxIndex(0));
+        storage_body::write(
+            rwtx,
+            1,
+            &BodyForStorage {
+                base_tx_id: 1.into(),
+                tx_amount: 2,
+                ommers: Default::default(),
+                withdrawals: None,
+            },
+        )
+        .unwrap();
+        assert_eq!(storage_body::next_base_tx_id(rwtx).unwrap(), TxIndex(3));
+
+        tx::write_checked(rwtx, 1, &txs).unwrap();
+
+        // Overlapping ranges are rejected and left intact
+        assert!(tx::write_checked(rwtx, 2, &txs).is_err());
+        assert!(tx::write_checked(rwtx, 0, &txs).is_err());
+        assert_eq!(tx::read(rwtx, 1, 2).unwrap(), txs);
+        assert_eq!(tx::read(rwtx, 3, 1).unwrap(), []);
+
+        // Adjacent ranges are fine
+        tx::write_checked(rwtx, 0, &txs[..1]).unwrap();
+        tx::write_checked(rwtx, 3, &txs).unwrap();
+        tx::write_checked(rwtx, 1, &[]).unwrap();
+        assert_eq!(tx::read(rwtx, 0, 5).unwrap().len(), 5);
+    }
+
+    #[test]
+    fn recent_blocks() {
+        let db = new_mem_chaindata().unwrap();
+        let rwtx = db.begin_mutable().unwrap();
+        let rwtx = &rwtx;
+
+        assert!(read_recent_blocks(rwtx, 3).unwrap().is_empty());
+
+        let mut blocks = vec![];
+        let mut base_tx_id = 0;
+        for number in (0..4).map(BlockNumber) {
+            let transactions = (base_tx_id..base_tx_id + number.0)
+                .map(legacy_tx)
+                .collect::<Vec<_>>();
+            let header = BlockHeader {
+                number,
+                ..Default::default()
+            };
+
+            rwtx.set(tables::CanonicalHeader, number, header.hash())
+                .unwrap();
+            rwtx.set(tables::Header, number, header.clone()).unwrap();
+            write_body_with_txs(rwtx, number, base_tx_id, &transactions);
+            base_tx_id += transactions.len() as u64;
+
+            blocks.push(Block {
+                header,
+                transactions,
+                ommers: Default::default(),
+            });
+        }
+        blocks.reverse();
+
+        assert!(read_recent_blocks(rwtx, 0).unwrap().is_empty());
+        assert_eq!(read_recent_blocks(rwtx, 2).unwrap(), blocks[..2]);
+        assert_eq!(read_recent_blocks(rwtx, 10).unwrap(), blocks);
+
+        // Headers without bodies yet are skipped
+        for number in (4..6).map(BlockNumber) {
+            let header = BlockHeader {
+                number,
+                ..Default::default()
+            };
+            rwtx.set(tables::CanonicalHeader, number, header.hash())
+                .unwrap();
+            rwtx.set(tables::Header, number, header).unwrap();
+        }
+        assert_eq!(read_recent_blocks(rwtx, 2).unwrap(), blocks[..2]);
+        assert_eq!(read_recent_blocks(rwtx, 10).unwrap(), blocks);
+
+        // Gap in bodies stops the walk
+        rwtx.del(tables::BlockBody, 2.into(), None).unwrap();
+        assert_eq!(read_recent_blocks(rwtx, 10).unwrap(), blocks[..1]);
+    }
+
+    #[test]
+    fn checksum_range() {
+        let db = new_mem_chaindata().unwrap();
+        let rwtx = db.begin_mutable().unwrap();
+        let rwtx = &rwtx;
+
+        let mut hashes = vec![];
+        for number in (0..4).map(BlockNumber) {
+            let hash = H256::repeat_byte(number.0 as u8 + 1);
+            hashes.push(hash);
+            rwtx.set(tables::CanonicalHeader, number, hash).unwrap();
+            storage_body::write(
+                rwtx,
+                number,
+                &BodyForStorage {
+                    base_tx_id: number.0.into(),
+                    tx_amount: 0,
+                    ommers: Default::default(),
+                    withdrawals: None,
+                },
+            )
+            .unwrap();
+        }
+
+        let checksum = range_checksum(rwtx, 1, 3, false).unwrap();
+        assert_eq!(
+            checksum,
+            keccak256(
+                hashes[1..]
+                    .iter()
+                    .flat_map(|hash| hash.0)
+                    .collect::<Vec<_>>()
+            )
+        );
+        assert_eq!(range_checksum(rwtx, 1, 3, false).unwrap(), checksum);
+        assert_ne!(range_checksum(rwtx, 0, 3, false).unwrap(), checksum);
+
+        let empty_body_hash = keccak256(hex_literal::hex!("c2c0c0"));
+        assert_eq!(
+            range_checksum(rwtx, 1, 3, true).unwrap(),
+            keccak256(
+                hashes[1..]
+                    .iter()
+                    .flat_map(|hash| [hash.0, empty_body_hash.0].concat())
+                    .collect::<Vec<_>>()
+            )
+        );
+
+        // Different canonical chain gives different digest
+        rwtx.set(tables::CanonicalHeader, 2.into(), H256::random())
+            .unwrap();
+        assert_ne!(range_checksum(rwtx, 1, 3, false).unwrap(), checksum);
+
+        // Missing blocks are an error
+        assert!(range_checksum(rwtx, 1, 4, false).is_err());
+    }
+
+    #[test]
+    fn canonical_header_range() {
+        let db = new_mem_chaindata().unwrap();
+        let rwtx = db.begin_mutable().unwrap();
+        let rwtx = &rwtx;
+
+        for number in [0, 1, 2, 3, 5].map(BlockNumber) {
+            let header = BlockHeader {
+                number,
+                ..Default::default()
+            };
+            rwtx.set(tables::CanonicalHeader, number, header.hash())
+                .unwrap();
+            rwtx.set(tables::Header, number, header).unwrap();
+        }
+        rwtx.set(tables::CanonicalHeader, 4.into(), H256::random())
+            .unwrap();
+
+        let numbers = |from: u64, to: u64| {
+            header::read_canonical_range(rwtx, from, to)
+                .unwrap()
+                .into_iter()
+                .map(|(number, header)| {
+                    assert_eq!(number, header.number);
+                    number.0
+                })
+                .collect::<Vec<_>>()
+        };
+
+        assert!(header::stored_header_hash(rwtx, 3).unwrap());
+        assert!(!header::stored_header_hash(rwtx, 4).unwrap());
+        assert!(!header::stored_header_hash(rwtx, 6).unwrap());
+
+        assert_eq!(numbers(1, 2), vec![1, 2]);
+        // Header for block 4 is missing
+        assert_eq!(numbers(2, 5), vec![2, 3]);
+        assert_eq!(numbers(5, 10), vec![5]);
+        assert_eq!(numbers(6, 10), vec![]);
+        assert_eq!(numbers(3, 2), vec![]);
+    }
+
+    #[test]
+    fn uncle_rewards() {
+        let db = new_mem_chaindata().unwrap();
+        let rwtx = db.begin_mutable().unwrap();
+        let rwtx = &rwtx;
+
+        let ommer1 = BlockHeader {
+            number: 9.into(),
+            ..Default::default()
+        };
+        let ommer2 = BlockHeader {
+            number: 8.into(),
+            extra_data: Bytes::from_static(b"ommer"),
+            ..Default::default()
+        };
+        storage_body::write(
+            rwtx,
+            10,
+            &BodyForStorage {
+                base_tx_id: 1.into(),
+                tx_amount: 0,
+                ommers: [ommer1.clone(), ommer2.clone()].into_iter().collect(),
+                withdrawals: None,
+            },
+        )
+        .unwrap();
+
+        let schedule = crate::consensus::BlockSchedule(
+            [
+                (BlockNumber(0), U256::from(5 * ETHER)),
+                (BlockNumber(10), U256::from(3 * ETHER)),
+            ]
+            .into_iter()
+            .collect(),
+        );
+
+        assert_eq!(
+            super::uncle_rewards(rwtx, 10, &schedule).unwrap(),
+            vec![
+                (ommer1.hash(), U256::from(3 * ETHER * 7 / 8)),
+                (ommer2.hash(), U256::from(3 * ETHER * 6 / 8)),
+            ]
+        );
+        assert!(super::uncle_rewards(rwtx, 11, &schedule).is_err());
+    }
+
+    #[test]
+    fn common_ancestor() {
+        let db = new_mem_chaindata().unwrap();
+        let rwtx = db.begin_mutable().unwrap();
+        let rwtx = &rwtx;
+
+        let mut canonical = vec![];
+        let mut parent_hash = H256::zero();
+        for number in 0..5_u64 {
+            let header = BlockHeader {
+                parent_hash,
+                number: number.into(),
+                ..Default::default()
+            };
+            let hash = header.hash();
+            rwtx.set(tables::CanonicalHeader, number.into(), hash)
+                .unwrap();
+            parent_hash = hash;
+            canonical.push((hash, header));
+        }
+
+        // Fork off block #2
+        let mut fork = canonical[..3].to_vec();
+        let mut parent_hash = canonical[2].0;
+        for number in 3..6_u64 {
+            let header = BlockHeader {
+                parent_hash,
+                number: number.into(),
+                extra_data: Bytes::from_static(b"fork"),
+                ..Default::default()
+            };
+            parent_hash = header.hash();
+            fork.push((parent_hash, header));
+        }
+
+        assert_eq!(
+            find_common_ancestor(rwtx, &canonical).unwrap(),
+            Some((4.into(), canonical[4].0))
+        );
+        assert_eq!(
+            find_common_ancestor(rwtx, &fork).unwrap(),
+            Some((2.into(), canonical[2].0))
+        );
+        assert_eq!(
+            find_common_ancestor(rwtx, &fork[3..]).unwrap(),
+            Some((2.into(), canonical[2].0))
+        );
+        assert_eq!(find_common_ancestor(rwtx, &fork[4..]).unwrap(), None);
     }
 }
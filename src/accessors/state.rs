@@ -13,18 +13,71 @@ pub mod account {
         address_to_find: Address,
         block_number: Option<BlockNumber>,
     ) -> anyhow::Result<Option<Account>> {
-        let changeset_block = if let Some(block_number) = block_number {
-            super::history_index::find_next_block(
-                tx,
-                tables::AccountHistory,
-                address_to_find,
-                block_number,
-            )?
+        let changeset_block = find_changeset_block(tx, address_to_find, block_number)?;
+
+        read_inner(tx, address_to_find, changeset_block)
+    }
+
+    /// Nonce of the account, zero if it does not exist.
+    /// Current state is read without decoding the whole account.
+    pub fn read_nonce<K: TransactionKind, E: EnvironmentKind>(
+        tx: &MdbxTransaction<'_, K, E>,
+        address: Address,
+        block_number: Option<BlockNumber>,
+    ) -> anyhow::Result<u64> {
+        let nonce = if let Some(changeset_block) = find_changeset_block(tx, address, block_number)?
+        {
+            read_inner(tx, address, Some(changeset_block))?.map(|account| account.nonce)
         } else {
-            None
+            read_encoded(tx, address)?
+                .map(|encoded| Account::decode_nonce_from_storage(&encoded))
+                .transpose()?
+                .flatten()
         };
 
-        read_inner(tx, address_to_find, changeset_block)
+        Ok(nonce.unwrap_or(0))
+    }
+
+    /// Balance of the account, zero if it does not exist.
+    /// Current state is read without decoding the whole account.
+    pub fn read_balance<K: TransactionKind, E: EnvironmentKind>(
+        tx: &MdbxTransaction<'_, K, E>,
+        address: Address,
+        block_number: Option<BlockNumber>,
+    ) -> anyhow::Result<U256> {
+        let balance =
+            if let Some(changeset_block) = find_changeset_block(tx, address, block_number)? {
+                read_inner(tx, address, Some(changeset_block))?.map(|account| account.balance)
+            } else {
+                read_encoded(tx, address)?
+                    .map(|encoded| Account::decode_balance_from_storage(&encoded))
+                    .transpose()?
+                    .flatten()
+            };
+
+        Ok(balance.unwrap_or(U256::ZERO))
+    }
+
+    fn find_changeset_block<K: TransactionKind, E: EnvironmentKind>(
+        tx: &MdbxTransaction<'_, K, E>,
+        address: Address,
+        block_number: Option<BlockNumber>,
+    ) -> anyhow::Result<Option<BlockNumber>> {
+        if let Some(block_number) = block_number {
+            super::history_index::find_next_block(tx, tables::AccountHistory, address, block_number)
+        } else {
+            Ok(None)
+        }
+    }
+
+    fn read_encoded<K: TransactionKind, E: EnvironmentKind>(
+        tx: &MdbxTransaction<'_, K, E>,
+        address: Address,
+    ) -> anyhow::Result<Option<Vec<u8>>> {
+        tx.get(
+            tables::Account.erased(),
+            tables::ErasedTable::<tables::Account>::encode_key(address).to_vec(),
+        )
     }
 
     fn read_inner<K: TransactionKind, E: EnvironmentKind>(
@@ -170,7 +223,7 @@ pub mod storage {
         read_inner(tx, address, location_to_find, changeset_block)
     }
 
-    fn read_inner<K: TransactionKind, E: EnvironmentKind>(
+    pub(super) fn read_inner<K: TransactionKind, E: EnvironmentKind>(
         tx: &MdbxTransaction<'_, K, E>,
         address: Address,
         location_to_find: H256,
@@ -305,6 +358,236 @@ pub mod code {
                 .ok_or_else(|| format_err!("code expected but not found"))?)
         }
     }
+
+    /// Code of the account at the end of `block_number`, or at the latest state if `None`.
+    /// Returns `None` if the account does not exist, empty code for accounts without one.
+    pub fn read_for_account<K: TransactionKind, E: EnvironmentKind>(
+        tx: &MdbxTransaction<'_, K, E>,
+        address: Address,
+        block_number: Option<BlockNumber>,
+    ) -> anyhow::Result<Option<Bytes>> {
+        super::account::read(tx, address, block_number)?
+            .map(|account| read(tx, account.code_hash))
+            .transpose()
+    }
+}
+
+pub mod changeset {
+    use super::*;
+    use anyhow::Context;
+
+    /// Decode account pre-image as stored in `AccountChangeSet`, without the address prefix.
+    /// Returns `None` for an empty pre-image, i.e. the account did not exist before the block.
+    pub fn decode_account_change(bytes: &[u8]) -> anyhow::Result<Option<Account>> {
+        Account::decode_for_storage(bytes).context("malformed account change pre-image")
+    }
+
+    /// Decode storage pre-image as stored in `StorageChangeSet`: location followed by compact value.
+    pub fn decode_storage_change(bytes: &[u8]) -> anyhow::Result<tables::StorageChange> {
+        <tables::StorageChange as TableDecode>::decode(bytes)
+            .context("malformed storage change pre-image")
+    }
+}
+
+pub mod history {
+    use super::*;
+    use crate::{h256_to_u256, u256_to_h256};
+    use anyhow::format_err;
+    use std::collections::{BTreeMap, BTreeSet};
+
+    /// Account or storage slot changed over a range of blocks.
+    #[derive(Clone, Debug, PartialEq, Eq)]
+    pub enum StateDiffEntry {
+        /// `before` is `None` if the account was created in the range,
+        /// `after` is `None` if it was destroyed.
+        Account {
+            address: Address,
+            before: Option<Account>,
+            after: Option<Account>,
+        },
+        Storage {
+            address: Address,
+            location: H256,
+            before: U256,
+            after: U256,
+        },
+    }
+
+    /// Distinct addresses with account changes in the block, ordered by address.
+    pub fn touched_accounts_in_block<K: TransactionKind, E: EnvironmentKind>(
+        tx: &MdbxTransaction<'_, K, E>,
+        block_number: BlockNumber,
+    ) -> anyhow::Result<Vec<Address>> {
+        let mut addresses = BTreeSet::new();
+
+        let mut walker = tx
+            .cursor(tables::AccountChangeSet)?
+            .walk_dup(block_number, None);
+        while let Some(tables::AccountChange { address, .. }) = walker.next().transpose()? {
+            addresses.insert(address);
+        }
+
+        Ok(addresses.into_iter().collect())
+    }
+
+    /// Distinct storage slots with changes in the block, ordered by address and location.
+    pub fn touched_storage_keys_in_block<K: TransactionKind, E: EnvironmentKind>(
+        tx: &MdbxTransaction<'_, K, E>,
+        block_number: BlockNumber,
+    ) -> anyhow::Result<Vec<(Address, H256)>> {
+        let mut slots = BTreeSet::new();
+
+        let mut walker = tx
+            .cursor(tables::StorageChangeSet)?
+            .walk(Some(block_number));
+        while let Some((key, tables::StorageChange { location, .. })) = walker.next().transpose()? {
+            if key.block_number != block_number {
+                break;
+            }
+            slots.insert((key.address, location));
+        }
+
+        Ok(slots.into_iter().collect())
+    }
+
+    /// Blocks in which the storage slot changed, in ascending order, each paired with the value
+    /// the slot held before that block.
+    pub fn storage_slot_timeline<'db, 'tx, K: TransactionKind, E: EnvironmentKind>(
+        tx: &'tx MdbxTransaction<'db, K, E>,
+        address: Address,
+        location: U256,
+    ) -> impl Iterator<Item = anyhow::Result<(BlockNumber, U256)>> + 'tx
+    where
+        'db: 'tx,
+    {
+        TryGenIter::from(move || {
+            let location = u256_to_h256(location);
+
+            let mut change_blocks = tx
+                .cursor(tables::StorageHistory)?
+                .walk_chunks((address, location), None);
+            while let Some(block_number) = change_blocks.next().transpose()? {
+                let value = super::storage::read_inner(tx, address, location, Some(block_number))?;
+                yield (block_number, value);
+            }
+
+            Ok(())
+        })
+    }
+
+    /// State changed in blocks `from_block..=to_block`: values of accounts and storage slots
+    /// before `from_block` and after `to_block`, computed from changesets.
+    ///
+    /// Accounts are yielded first, then storage slots, each ordered by key.
+    /// Entries which were changed in the range but ended up with the same value are skipped.
+    pub fn state_diff<'db, 'tx, K: TransactionKind, E: EnvironmentKind>(
+        tx: &'tx MdbxTransaction<'db, K, E>,
+        from_block: BlockNumber,
+        to_block: BlockNumber,
+    ) -> impl Iterator<Item = anyhow::Result<StateDiffEntry>> + 'tx
+    where
+        'db: 'tx,
+    {
+        TryGenIter::from(move || {
+            // First pre-image in the range is the value before the range
+            let mut accounts = BTreeMap::new();
+            let mut walker = tx.cursor(tables::AccountChangeSet)?.walk(Some(from_block));
+            while let Some((block_number, tables::AccountChange { address, account })) =
+                walker.next().transpose()?
+            {
+                if block_number > to_block {
+                    break;
+                }
+                accounts.entry(address).or_insert(account);
+            }
+
+            for (address, before) in accounts {
+                let after = super::account::read(tx, address, Some(to_block))?;
+                if before != after {
+                    yield StateDiffEntry::Account {
+                        address,
+                        before,
+                        after,
+                    };
+                }
+            }
+
+            let mut slots = BTreeMap::new();
+            let mut walker = tx.cursor(tables::StorageChangeSet)?.walk(Some(from_block));
+            while let Some((key, tables::StorageChange { location, value })) =
+                walker.next().transpose()?
+            {
+                if key.block_number > to_block {
+                    break;
+                }
+                slots.entry((key.address, location)).or_insert(value);
+            }
+
+            for ((address, location), before) in slots {
+                let after =
+                    super::storage::read(tx, address, h256_to_u256(location), Some(to_block))?;
+                if before != after {
+                    yield StateDiffEntry::Storage {
+                        address,
+                        location,
+                        before,
+                        after,
+                    };
+                }
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Blocks in which a contract was deployed at the address, numbered as incarnations starting from 1.
+    ///
+    /// Incarnations are not stored in the database, so they are derived from account history:
+    /// a new incarnation starts whenever the account ends a block with code different from
+    /// what it had before that block, including when it did not exist.
+    pub fn incarnations_of<K: TransactionKind, E: EnvironmentKind>(
+        tx: &MdbxTransaction<'_, K, E>,
+        address: Address,
+    ) -> anyhow::Result<Vec<(BlockNumber, u64)>> {
+        let mut change_blocks = vec![];
+        let mut walker = tx
+            .cursor(tables::AccountHistory)?
+            .walk_chunks(address, None);
+        while let Some(block_number) = walker.next().transpose()? {
+            change_blocks.push(block_number);
+        }
+
+        let mut changesets = tx.cursor(tables::AccountChangeSet)?;
+        let mut pre_images = Vec::with_capacity(change_blocks.len());
+        for &block_number in &change_blocks {
+            pre_images.push(
+                changesets
+                    .find_account(block_number, address)?
+                    .ok_or_else(|| {
+                        format_err!("changeset for block #{block_number} does not contain account")
+                    })?,
+            );
+        }
+
+        let current = tx.get(tables::Account, address)?;
+
+        let mut incarnation = 0;
+        let mut incarnations = vec![];
+        for (i, (&block_number, pre_image)) in change_blocks.iter().zip(&pre_images).enumerate() {
+            let post_image = pre_images.get(i + 1).copied().unwrap_or(current);
+
+            if let Some(post_image) = post_image {
+                if post_image.code_hash != EMPTY_HASH
+                    && pre_image.map(|account| account.code_hash) != Some(post_image.code_hash)
+                {
+                    incarnation += 1;
+                    incarnations.push((block_number, incarnation));
+                }
+            }
+        }
+
+        Ok(incarnations)
+    }
 }
 
 pub mod history_index {
@@ -354,6 +637,35 @@ pub mod tests {
     };
     use hex_literal::hex;
 
+    #[test]
+    fn read_nonce_and_balance() {
+        let db = new_mem_chaindata().unwrap();
+        let txn = db.begin_mutable().unwrap();
+
+        let address = hex!("b000000000000000000000000000000000000008").into();
+        assert_eq!(account::read_nonce(&txn, address, None).unwrap(), 0);
+        assert_eq!(
+            account::read_balance(&txn, address, None).unwrap(),
+            U256::ZERO
+        );
+
+        txn.set(
+            tables::Account,
+            address,
+            Account {
+                nonce: 42,
+                balance: U256::new(1_000_000_000),
+                code_hash: H256::repeat_byte(0xaa),
+            },
+        )
+        .unwrap();
+        assert_eq!(account::read_nonce(&txn, address, None).unwrap(), 42);
+        assert_eq!(
+            account::read_balance(&txn, address, None).unwrap(),
+            U256::new(1_000_000_000)
+        );
+    }
+
     #[test]
     fn read_storage() {
         let db = new_mem_chaindata().unwrap();
@@ -395,6 +707,397 @@ pub mod tests {
         );
     }
 
+    #[test]
+    fn decode_changes() {
+        use crate::crypto::keccak256;
+
+        for account in [
+            Account {
+                nonce: 2,
+                balance: 1000.as_u256(),
+                code_hash: keccak256([1, 2, 3]),
+            },
+            Account {
+                nonce: 0,
+                balance: 0.as_u256(),
+                code_hash: EMPTY_HASH,
+            },
+        ] {
+            assert_eq!(
+                super::changeset::decode_account_change(&account.encode_for_storage()).unwrap(),
+                Some(account)
+            );
+        }
+
+        let created = tables::AccountChange {
+            address: Address::from_low_u64_be(1),
+            account: None,
+        }
+        .encode();
+        assert_eq!(
+            super::changeset::decode_account_change(&created[ADDRESS_LENGTH..]).unwrap(),
+            None
+        );
+
+        let change = tables::StorageChange {
+            location: H256::repeat_byte(0xab),
+            value: 0xdeadbeef_u64.as_u256(),
+        };
+        assert_eq!(
+            super::changeset::decode_storage_change(&change.clone().encode()).unwrap(),
+            change
+        );
+        assert!(super::changeset::decode_storage_change(&[0; 8]).is_err());
+    }
+
+    #[test]
+    fn touched_in_block() {
+        let db = new_mem_chaindata().unwrap();
+        let txn = db.begin_mutable().unwrap();
+
+        let a1 = Address::from_low_u64_be(1);
+        let a2 = Address::from_low_u64_be(2);
+        let a3 = Address::from_low_u64_be(3);
+        let loc1 = H256::from_low_u64_be(1);
+        let loc2 = H256::from_low_u64_be(2);
+
+        for (block_number, address) in [(1, a3), (1, a1), (2, a2)] {
+            txn.set(
+                tables::AccountChangeSet,
+                BlockNumber(block_number),
+                tables::AccountChange {
+                    address,
+                    account: None,
+                },
+            )
+            .unwrap();
+        }
+        for (block_number, address, location) in
+            [(1, a2, loc2), (1, a2, loc1), (1, a1, loc1), (2, a3, loc1)]
+        {
+            txn.set(
+                tables::StorageChangeSet,
+                tables::StorageChangeKey {
+                    block_number: BlockNumber(block_number),
+                    address,
+                },
+                tables::StorageChange {
+                    location,
+                    value: 0.as_u256(),
+                },
+            )
+            .unwrap();
+        }
+
+        assert_eq!(
+            super::history::touched_accounts_in_block(&txn, BlockNumber(1)).unwrap(),
+            vec![a1, a3]
+        );
+        assert_eq!(
+            super::history::touched_accounts_in_block(&txn, BlockNumber(3)).unwrap(),
+            vec![]
+        );
+        assert_eq!(
+            super::history::touched_storage_keys_in_block(&txn, BlockNumber(1)).unwrap(),
+            vec![(a1, loc1), (a2, loc1), (a2, loc2)]
+        );
+        assert_eq!(
+            super::history::touched_storage_keys_in_block(&txn, BlockNumber(2)).unwrap(),
+            vec![(a3, loc1)]
+        );
+    }
+
+    #[test]
+    fn storage_slot_timeline() {
+        let db = new_mem_chaindata().unwrap();
+        let txn = db.begin_mutable().unwrap();
+
+        let address = Address::from_low_u64_be(1);
+        let loc1 = H256::from_low_u64_be(1);
+        let loc2 = H256::from_low_u64_be(2);
+
+        txn.set(
+            tables::StorageHistory,
+            BitmapKey {
+                inner: (address, loc1),
+                block_number: 5.into(),
+            },
+            [2, 5].into_iter().collect(),
+        )
+        .unwrap();
+        txn.set(
+            tables::StorageHistory,
+            BitmapKey {
+                inner: (address, loc1),
+                block_number: u64::MAX.into(),
+            },
+            [9].into_iter().collect(),
+        )
+        .unwrap();
+        txn.set(
+            tables::StorageHistory,
+            BitmapKey {
+                inner: (address, loc2),
+                block_number: u64::MAX.into(),
+            },
+            [5].into_iter().collect(),
+        )
+        .unwrap();
+
+        for (block_number, location, value) in [
+            (2, loc1, 0x00),
+            (5, loc1, 0x10),
+            (5, loc2, 0x20),
+            (9, loc1, 0x30),
+        ] {
+            txn.set(
+                tables::StorageChangeSet,
+                tables::StorageChangeKey {
+                    block_number: BlockNumber(block_number),
+                    address,
+                },
+                tables::StorageChange {
+                    location,
+                    value: value.as_u256(),
+                },
+            )
+            .unwrap();
+        }
+
+        assert_eq!(
+            super::history::storage_slot_timeline(&txn, address, h256_to_u256(loc1))
+                .collect::<anyhow::Result<Vec<_>>>()
+                .unwrap(),
+            vec![
+                (BlockNumber(2), 0x00.as_u256()),
+                (BlockNumber(5), 0x10.as_u256()),
+                (BlockNumber(9), 0x30.as_u256()),
+            ]
+        );
+        assert_eq!(
+            super::history::storage_slot_timeline(&txn, address, h256_to_u256(loc2))
+                .collect::<anyhow::Result<Vec<_>>>()
+                .unwrap(),
+            vec![(BlockNumber(5), 0x20.as_u256())]
+        );
+        assert_eq!(
+            super::history::storage_slot_timeline(
+                &txn,
+                Address::from_low_u64_be(2),
+                h256_to_u256(loc1)
+            )
+            .count(),
+            0
+        );
+    }
+
+    #[test]
+    fn state_diff() {
+        let db = new_mem_chaindata().unwrap();
+        let txn = db.begin_mutable().unwrap();
+
+        let account = |balance: u64| Account {
+            nonce: 1,
+            balance: balance.as_u256(),
+            code_hash: EMPTY_HASH,
+        };
+        let created = Address::from_low_u64_be(1);
+        let destroyed = Address::from_low_u64_be(2);
+        let changed = Address::from_low_u64_be(3);
+        let outside = Address::from_low_u64_be(4);
+        let reverted = Address::from_low_u64_be(5);
+        let location = H256::from_low_u64_be(1);
+
+        for (address, changes, current) in [
+            (created, vec![(2, None)], Some(account(1))),
+            (destroyed, vec![(3, Some(account(1)))], None),
+            (
+                changed,
+                vec![(2, Some(account(1))), (5, Some(account(2)))],
+                Some(account(3)),
+            ),
+            (outside, vec![(1, Some(account(1)))], Some(account(2))),
+            (
+                reverted,
+                vec![(2, Some(account(1))), (3, Some(account(2)))],
+                Some(account(1)),
+            ),
+        ] {
+            txn.set(
+                tables::AccountHistory,
+                BitmapKey {
+                    inner: address,
+                    block_number: u64::MAX.into(),
+                },
+                changes.iter().map(|&(block, _)| block).collect(),
+            )
+            .unwrap();
+            for (block_number, account) in changes {
+                txn.set(
+                    tables::AccountChangeSet,
+                    BlockNumber(block_number),
+                    tables::AccountChange { address, account },
+                )
+                .unwrap();
+            }
+            if let Some(current) = current {
+                txn.set(tables::Account, address, current).unwrap();
+            }
+        }
+
+        txn.set(
+            tables::StorageHistory,
+            BitmapKey {
+                inner: (changed, location),
+                block_number: u64::MAX.into(),
+            },
+            [3, 6].into_iter().collect(),
+        )
+        .unwrap();
+        for (block_number, value) in [(3, 0x10), (6, 0x20)] {
+            txn.set(
+                tables::StorageChangeSet,
+                tables::StorageChangeKey {
+                    block_number: BlockNumber(block_number),
+                    address: changed,
+                },
+                tables::StorageChange {
+                    location,
+                    value: value.as_u256(),
+                },
+            )
+            .unwrap();
+        }
+        txn.set(tables::Storage, changed, (location, 0x30.as_u256()))
+            .unwrap();
+
+        assert_eq!(
+            super::history::state_diff(&txn, BlockNumber(2), BlockNumber(3))
+                .collect::<anyhow::Result<Vec<_>>>()
+                .unwrap(),
+            vec![
+                history::StateDiffEntry::Account {
+                    address: created,
+                    before: None,
+                    after: Some(account(1)),
+                },
+                history::StateDiffEntry::Account {
+                    address: destroyed,
+                    before: Some(account(1)),
+                    after: None,
+                },
+                history::StateDiffEntry::Account {
+                    address: changed,
+                    before: Some(account(1)),
+                    after: Some(account(2)),
+                },
+                history::StateDiffEntry::Storage {
+                    address: changed,
+                    location,
+                    before: 0x10.as_u256(),
+                    after: 0x20.as_u256(),
+                },
+            ]
+        );
+        assert_eq!(
+            super::history::state_diff(&txn, BlockNumber(7), BlockNumber(9)).count(),
+            0
+        );
+    }
+
+    #[test]
+    fn read_code_for_account() {
+        let db = new_mem_chaindata().unwrap();
+        let txn = db.begin_mutable().unwrap();
+
+        let eoa = Address::from_low_u64_be(1);
+        let contract = Address::from_low_u64_be(2);
+        let code = bytes::Bytes::from_static(&[0x60, 0x00]);
+        let code_hash = H256::from_low_u64_be(0xc0de);
+
+        txn.set(
+            tables::Account,
+            eoa,
+            Account {
+                nonce: 1,
+                balance: 1.as_u256(),
+                code_hash: EMPTY_HASH,
+            },
+        )
+        .unwrap();
+        txn.set(
+            tables::Account,
+            contract,
+            Account {
+                nonce: 1,
+                balance: 0.as_u256(),
+                code_hash,
+            },
+        )
+        .unwrap();
+        txn.set(tables::Code, code_hash, code.clone()).unwrap();
+
+        assert_eq!(
+            super::code::read_for_account(&txn, contract, None).unwrap(),
+            Some(code)
+        );
+        assert_eq!(
+            super::code::read_for_account(&txn, eoa, None).unwrap(),
+            Some(bytes::Bytes::new())
+        );
+        assert_eq!(
+            super::code::read_for_account(&txn, Address::from_low_u64_be(3), None).unwrap(),
+            None
+        );
+    }
+
+    #[test]
+    fn incarnations_of() {
+        let db = new_mem_chaindata().unwrap();
+        let txn = db.begin_mutable().unwrap();
+
+        let address = Address::from_low_u64_be(1);
+        let contract = |code: u64, balance: u64| Account {
+            nonce: 1,
+            balance: balance.as_u256(),
+            code_hash: H256::from_low_u64_be(code),
+        };
+
+        txn.set(
+            tables::AccountHistory,
+            BitmapKey {
+                inner: address,
+                block_number: u64::MAX.into(),
+            },
+            [3, 5, 8, 10].into_iter().collect(),
+        )
+        .unwrap();
+        // Deployed in 3, balance changed in 5, self-destructed in 8, deployed again in 10
+        for (block_number, account) in [
+            (3, None),
+            (5, Some(contract(0xa, 0))),
+            (8, Some(contract(0xa, 1))),
+            (10, None),
+        ] {
+            txn.set(
+                tables::AccountChangeSet,
+                BlockNumber(block_number),
+                tables::AccountChange { address, account },
+            )
+            .unwrap();
+        }
+        txn.set(tables::Account, address, contract(0xb, 0)).unwrap();
+
+        assert_eq!(
+            super::history::incarnations_of(&txn, address).unwrap(),
+            vec![(BlockNumber(3), 1), (BlockNumber(10), 2)]
+        );
+        assert_eq!(
+            super::history::incarnations_of(&txn, Address::from_low_u64_be(2)).unwrap(),
+            vec![]
+        );
+    }
+
     #[test]
     fn find_next_block() {
         let db = new_mem_chaindata().unwrap();
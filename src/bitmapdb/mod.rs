@@ -51,6 +51,59 @@ where
     Ok(out.unwrap_or_default())
 }
 
+/// Like [get], but reads at most `max_chunks` chunks.
+/// If there is more history to read, also returns the block to continue from.
+pub fn get_bounded<T, K, TK, E>(
+    tx: &MdbxTransaction<'_, TK, E>,
+    table: T,
+    key: K,
+    range: RangeInclusive<BlockNumber>,
+    max_chunks: usize,
+) -> anyhow::Result<(RoaringTreemap, Option<BlockNumber>)>
+where
+    TK: TransactionKind,
+    E: EnvironmentKind,
+    K: Clone + PartialEq + Send,
+    BitmapKey<K>: TableDecode,
+    T: Table<Key = BitmapKey<K>, Value = RoaringTreemap, SeekKey = BitmapKey<K>>,
+{
+    let mut out: Option<RoaringTreemap> = None;
+    let from = *range.start();
+    let to = *range.end();
+
+    let s = tx
+        .cursor(table)?
+        .walk(Some(BitmapKey {
+            inner: key.clone(),
+            block_number: from,
+        }))
+        .take_while(ttw(|(BitmapKey { inner, .. }, _)| *inner == key));
+
+    pin!(s);
+
+    let mut chunks = 0;
+    let mut next_from = from;
+    while let Some((BitmapKey { block_number, .. }, v)) = s.next().transpose()? {
+        if chunks == max_chunks {
+            return Ok((out.unwrap_or_default(), Some(next_from)));
+        }
+        chunks += 1;
+
+        if let Some(total) = out {
+            out = Some(total | v);
+        } else {
+            out = Some(v);
+        }
+
+        if block_number >= to {
+            break;
+        }
+        next_from = block_number + 1_u8;
+    }
+
+    Ok((out.unwrap_or_default(), None))
+}
+
 impl<'txn, TK, K, T> MdbxCursor<'txn, TK, T>
 where
     TK: TransactionKind,
@@ -202,6 +255,83 @@ impl Iterator for ChunksWithKeys {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::kv::{new_mem_chaindata, tables};
+
+    #[test]
+    fn get_bounded() {
+        let db = new_mem_chaindata().unwrap();
+        let txn = db.begin_mutable().unwrap();
+
+        let address = Address::from_low_u64_be(1);
+        for i in 0..10_u64 {
+            let mut chunk = RoaringTreemap::create();
+            for block in i * 10..i * 10 + 10 {
+                chunk.add(block);
+            }
+            txn.set(
+                tables::AccountHistory,
+                BitmapKey {
+                    inner: address,
+                    block_number: BlockNumber(if i == 9 { u64::MAX } else { i * 10 + 9 }),
+                },
+                chunk,
+            )
+            .unwrap();
+        }
+
+        let mut pages = vec![];
+        let mut from = BlockNumber(0);
+        loop {
+            let (bitmap, next) = super::get_bounded(
+                &txn,
+                tables::AccountHistory,
+                address,
+                from..=BlockNumber(u64::MAX),
+                3,
+            )
+            .unwrap();
+            pages.push(bitmap.cardinality());
+            if let Some(next) = next {
+                from = next;
+            } else {
+                break;
+            }
+        }
+        assert_eq!(pages, vec![30, 30, 30, 10]);
+
+        let (bitmap, next) = super::get_bounded(
+            &txn,
+            tables::AccountHistory,
+            address,
+            BlockNumber(15)..=BlockNumber(35),
+            3,
+        )
+        .unwrap();
+        assert_eq!(next, None);
+        assert_eq!(bitmap.minimum(), Some(10));
+        assert_eq!(bitmap.maximum(), Some(39));
+        assert_eq!(
+            bitmap,
+            super::get(
+                &txn,
+                tables::AccountHistory,
+                address,
+                BlockNumber(15)..=BlockNumber(35)
+            )
+            .unwrap()
+        );
+
+        let (bitmap, next) = super::get_bounded(
+            &txn,
+            tables::AccountHistory,
+            address,
+            BlockNumber(15)..=BlockNumber(35),
+            2,
+        )
+        .unwrap();
+        assert_eq!(next, Some(BlockNumber(30)));
+        assert_eq!(bitmap.maximum(), Some(29));
+    }
 
     #[test]
     fn chunks() {
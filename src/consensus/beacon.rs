@@ -45,6 +45,7 @@ impl EngineApiServer for EngineApiServerImpl {
         debug!("Received fork choice information: {fork_choice_state:?}");
         let _ = self.chain_tip_sender.send(ExternalForkChoice {
             head_block: fork_choice_state.head_block_hash,
+            safe_block: fork_choice_state.safe_block_hash,
             finalized_block: fork_choice_state.finalized_block_hash,
         });
         Ok(ForkchoiceUpdatedResponse {
@@ -118,6 +119,7 @@ impl BeaconConsensus {
     ) -> Self {
         let (chain_tip_sender, receiver) = tokio::sync::watch::channel(ExternalForkChoice {
             head_block: H256::zero(),
+            safe_block: H256::zero(),
             finalized_block: H256::zero(),
         });
         Self {
@@ -254,9 +256,7 @@ impl Consensus for BeaconConsensus {
                 return Err(ValidationError::TooManyOmmers.into());
             }
 
-            if header.difficulty != U256::ZERO {
-                return Err(ValidationError::WrongDifficulty.into());
-            }
+            self.validate_difficulty(header, parent)?;
 
             if header.nonce != H64::zero() {
                 return Err(ValidationError::WrongHeaderNonce {
@@ -270,6 +270,19 @@ impl Consensus for BeaconConsensus {
         Ok(())
     }
 
+    fn validate_difficulty(&self, header: &BlockHeader, _: &BlockHeader) -> Result<(), DuoError> {
+        if self
+            .since
+            .map(|since| header.number >= since)
+            .unwrap_or(true)
+            && header.difficulty != U256::ZERO
+        {
+            return Err(ValidationError::WrongDifficulty.into());
+        }
+
+        Ok(())
+    }
+
     fn get_beneficiary(&self, header: &BlockHeader) -> Address {
         match self.beneficiary_schedule.for_block(header.number) {
             BeneficiaryFunction::Simple => header.beneficiary,
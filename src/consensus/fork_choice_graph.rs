@@ -71,26 +71,80 @@ impl ForkChoiceGraph {
     }
 
     #[inline]
-    pub fn insert_with_hash(&mut self, hash: H256, header: BlockHeader) {
+    /// Insert header, unless its parent is in the graph at a height other than one block below it.
+    /// Children inserted before it which are not one block above it are removed from the graph.
+    /// Inserting a header which is already in the graph is a no-op, while a different header
+    /// under the same hash is rejected.
+    /// Returns whether the header is in the graph.
+    pub fn insert_with_hash(&mut self, hash: H256, header: BlockHeader) -> bool {
+        if let Some(existing) = self.raw.peek(&hash) {
+            return *existing == header;
+        }
+
         if self.q.contains_key(&hash) {
-            return;
+            return true;
+        }
+
+        if let Some(parent) = self.raw.peek(&header.parent_hash) {
+            if parent.number + 1_u8 != header.number {
+                return false;
+            }
         }
 
+        self.drop_distant_children(hash, header.number);
+
         self.skip_list
             .entry(header.parent_hash)
             .or_insert_with(HashSet::new)
             .insert(hash);
         self.raw.insert(hash, header);
         self.q.insert(hash, ());
+
+        true
+    }
+
+    /// Remove headers naming `parent_hash` as parent which are not at `parent_number + 1`.
+    /// Their own children stay in the graph, detached.
+    fn drop_distant_children(&mut self, parent_hash: H256, parent_number: BlockNumber) {
+        let distant = match self.skip_list.peek(&parent_hash) {
+            Some(children) => children
+                .iter()
+                .filter(|child| {
+                    self.raw
+                        .peek(*child)
+                        .map_or(false, |child| child.number != parent_number + 1_u8)
+                })
+                .copied()
+                .collect::<Vec<_>>(),
+            None => return,
+        };
+        if distant.is_empty() {
+            return;
+        }
+
+        for child in &distant {
+            self.raw.remove(child);
+            self.q.remove(child);
+        }
+        if let Some(children) = self.skip_list.get_mut(&parent_hash) {
+            children.retain(|child| !distant.contains(child));
+            if children.is_empty() {
+                self.skip_list.remove(&parent_hash);
+            }
+        }
+        // Cached chains may run through removed headers
+        self.chains.clear();
     }
 
     #[inline]
-    pub fn insert(&mut self, header: BlockHeader) {
+    pub fn insert(&mut self, header: BlockHeader) -> bool {
         let hash = header.hash();
-        self.insert_with_hash(hash, header);
+        self.insert_with_hash(hash, header)
     }
 
     /// Find chain head using depth-first search algorithm.
+    /// Of chains with equal total difficulty, the one with the greatest head hash is picked,
+    /// so the result does not depend on the order headers were inserted in.
     pub fn chain_head(&mut self) -> Option<H256> {
         let mut roots = HashSet::new();
 
@@ -119,7 +173,11 @@ impl ForkChoiceGraph {
             self.chains.insert(root, (td, depth, current));
         }
 
-        if let Some((head_hash, (_, _, _))) = self.chains.iter().max_by_key(|(_, (td, _, _))| *td) {
+        if let Some((head_hash, (_, _, _))) = self
+            .chains
+            .iter()
+            .max_by_key(|(hash, (td, _, _))| (*td, **hash))
+        {
             let header = self.raw.get(head_hash).unwrap();
 
             self.head = Link {
@@ -133,6 +191,42 @@ impl ForkChoiceGraph {
         }
     }
 
+    /// Head of the heaviest chain descending from `anchor`, which itself is not in the graph.
+    /// Unlike [Self::chain_head], chains not attached to `anchor` are ignored. Ties are broken the same way.
+    pub fn chain_head_from(&mut self, anchor: H256) -> Option<H256> {
+        let roots = self
+            .q
+            .iter()
+            .map(|(hash, _)| *hash)
+            .filter(|hash| !self.skip_list.contains_key(hash) && self.raw.contains_key(hash))
+            .collect::<Vec<_>>();
+
+        let mut best: Option<(U256, Depth, H256)> = None;
+        for root in roots {
+            let mut current = root;
+            let mut td = U256::ZERO;
+            let mut depth = 0;
+
+            while let Some(header) = self.raw.get(&current) {
+                td += header.difficulty;
+                current = header.parent_hash;
+                depth += 1;
+            }
+
+            if current == anchor
+                && best
+                    .map(|(best_td, _, best_root)| (td, root) > (best_td, best_root))
+                    .unwrap_or(true)
+            {
+                best = Some((td, depth, root));
+            }
+        }
+
+        let (td, depth, head_hash) = best?;
+        self.chains.insert(head_hash, (td, depth, anchor));
+        Some(head_hash)
+    }
+
     pub fn backtrack(&mut self, tail: &H256) -> Vec<(H256, BlockHeader)> {
         let cap = self
             .chains
@@ -182,6 +276,206 @@ mod tests {
     use bytes::Bytes;
     use std::collections::HashMap;
 
+    #[test]
+    fn chain_head_from() {
+        let mut graph = ForkChoiceGraph::new();
+
+        let chain = |parent_hash: H256, difficulty: u64, len: u64| {
+            let mut parent_hash = parent_hash;
+            (1..=len)
+                .map(|number| {
+                    let header = BlockHeader {
+                        parent_hash,
+                        number: BlockNumber(number),
+                        difficulty: U256::from(difficulty),
+                        ..Default::default()
+                    };
+                    parent_hash = header.hash();
+                    (parent_hash, header)
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let anchor = H256::repeat_byte(1);
+        let attached = chain(anchor, 1, 3);
+        // Heavier, but does not descend from anchor
+        let detached = chain(H256::repeat_byte(2), 100, 3);
+
+        graph.extend(attached.clone());
+        graph.extend(detached.clone());
+
+        assert_eq!(graph.chain_head_from(anchor), Some(attached[2].0));
+        assert_eq!(graph.backtrack(&attached[2].0), attached);
+        assert_eq!(graph.chain_head_from(anchor), None);
+        assert_eq!(graph.chain_head(), Some(detached[2].0));
+    }
+
+    #[test]
+    fn reject_distant_parent() {
+        let mut graph = ForkChoiceGraph::new();
+
+        let mut parent_hash = H256::zero();
+        let chain = (1..=3)
+            .map(|number| {
+                let header = BlockHeader {
+                    parent_hash,
+                    number: BlockNumber(number),
+                    difficulty: U256::ONE,
+                    ..Default::default()
+                };
+                parent_hash = header.hash();
+                (parent_hash, header)
+            })
+            .collect::<Vec<_>>();
+        for (hash, header) in chain.clone() {
+            assert!(graph.insert_with_hash(hash, header));
+        }
+
+        // Claims block #1 as parent, many blocks away
+        let short_circuit = BlockHeader {
+            parent_hash: chain[0].0,
+            number: BlockNumber(10),
+            difficulty: U256::from(100_u64),
+            ..Default::default()
+        };
+        assert!(!graph.insert(short_circuit.clone()));
+        assert!(!graph.contains(short_circuit.hash()));
+
+        // Parent not in the graph cannot be checked yet
+        let detached = BlockHeader {
+            parent_hash: H256::repeat_byte(1),
+            number: BlockNumber(10),
+            ..Default::default()
+        };
+        assert!(graph.insert(detached));
+
+        assert_eq!(graph.chain_head(), Some(chain[2].0));
+    }
+
+    #[test]
+    fn reject_distant_child_inserted_first() {
+        let mut graph = ForkChoiceGraph::new();
+
+        let parent = BlockHeader {
+            number: BlockNumber(1),
+            difficulty: U256::ONE,
+            ..Default::default()
+        };
+        let parent_hash = parent.hash();
+        let child = BlockHeader {
+            parent_hash,
+            number: BlockNumber(2),
+            difficulty: U256::ONE,
+            ..Default::default()
+        };
+        // Claims block #1 as parent, many blocks away, and arrives before it
+        let short_circuit = BlockHeader {
+            parent_hash,
+            number: BlockNumber(10),
+            difficulty: U256::from(100_u64),
+            ..Default::default()
+        };
+        let grandchild = BlockHeader {
+            parent_hash: short_circuit.hash(),
+            number: BlockNumber(11),
+            difficulty: U256::ONE,
+            ..Default::default()
+        };
+
+        assert!(graph.insert(short_circuit.clone()));
+        assert!(graph.insert(grandchild.clone()));
+        assert!(graph.insert(child.clone()));
+        assert!(graph.insert(parent.clone()));
+        assert!(!graph.contains(short_circuit.hash()));
+        assert!(graph.contains(child.hash()));
+
+        // Header above the removed one is left detached from the chain
+        assert_eq!(graph.chain_head_from(H256::zero()), Some(child.hash()));
+        assert_eq!(
+            graph.backtrack(&child.hash()),
+            vec![(parent_hash, parent), (child.hash(), child)]
+        );
+        assert_eq!(graph.chain_head(), Some(grandchild.hash()));
+    }
+
+    #[test]
+    fn duplicate_header() {
+        let mut graph = ForkChoiceGraph::new();
+
+        let header = BlockHeader {
+            number: BlockNumber(1),
+            difficulty: U256::ONE,
+            ..Default::default()
+        };
+        let hash = header.hash();
+
+        assert!(graph.insert_with_hash(hash, header.clone()));
+        assert!(graph.insert_with_hash(hash, header.clone()));
+        graph.extend([(hash, header.clone())]);
+        assert_eq!(graph.len(), 1);
+
+        // Same hash, different contents
+        let colliding = BlockHeader {
+            extra_data: b"colliding".to_vec().into(),
+            ..header.clone()
+        };
+        assert!(!graph.insert_with_hash(hash, colliding));
+        assert_eq!(graph.len(), 1);
+
+        assert_eq!(graph.chain_head(), Some(hash));
+        assert_eq!(graph.backtrack(&hash), vec![(hash, header)]);
+    }
+
+    #[test]
+    fn insertion_order() {
+        let fork = |extra_data: &'static [u8]| {
+            let mut parent_hash = H256::zero();
+            (1..=3)
+                .map(|number| {
+                    let header = BlockHeader {
+                        parent_hash,
+                        number: BlockNumber(number),
+                        difficulty: U256::ONE,
+                        extra_data: Bytes::from_static(extra_data),
+                        ..Default::default()
+                    };
+                    parent_hash = header.hash();
+                    (parent_hash, header)
+                })
+                .collect::<Vec<_>>()
+        };
+        // Equal total difficulty
+        let a = fork(b"a");
+        let b = fork(b"b");
+
+        let orders: [Vec<(H256, BlockHeader)>; 4] = [
+            a.iter().chain(&b).cloned().collect(),
+            b.iter().chain(&a).cloned().collect(),
+            a.iter().chain(&b).rev().cloned().collect(),
+            a.iter()
+                .zip(&b)
+                .flat_map(|(a, b)| [b.clone(), a.clone()])
+                .collect(),
+        ];
+
+        let mut outputs = vec![];
+        for headers in orders {
+            let mut graph = ForkChoiceGraph::new();
+            graph.extend(headers.clone());
+            let head = graph.chain_head().unwrap();
+            let from_anchor = {
+                let mut graph = ForkChoiceGraph::new();
+                graph.extend(headers);
+                graph.chain_head_from(H256::zero()).unwrap()
+            };
+            assert_eq!(head, from_anchor);
+            outputs.push(graph.backtrack(&head));
+        }
+
+        assert!(outputs.windows(2).all(|w| w[0] == w[1]));
+        assert!(outputs[0] == a || outputs[0] == b);
+    }
+
     #[test]
     fn test_max_td() {
         const FORKED_EXTRA_DATA: &[u8] = b"forked";
@@ -258,7 +552,7 @@ mod tests {
         assert_eq!(extra_data_cache[&canonical_head], CANONICAL_EXTRA_DATA);
 
         // Insert more blocks from the forked chain.
-        for number in (30..40u64).map(BlockNumber) {
+        for number in (20..30u64).map(BlockNumber) {
             (insert_header)(
                 &mut graph,
                 &mut extra_data_cache,
@@ -51,9 +51,10 @@ impl ConsensusState {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
 pub struct ExternalForkChoice {
     pub head_block: H256,
+    pub safe_block: H256,
     pub finalized_block: H256,
 }
 
@@ -107,6 +108,19 @@ pub trait Consensus: Debug + Send + Sync + 'static {
         true
     }
 
+    /// Checks header difficulty against the consensus rules given its parent.
+    /// Engines also do this as part of `validate_block_header`.
+    ///
+    /// See YP Section 4.3.4 "Block Header Validity", Eq (50).
+    #[allow(unused_variables)]
+    fn validate_difficulty(
+        &self,
+        header: &BlockHeader,
+        parent: &BlockHeader,
+    ) -> Result<(), DuoError> {
+        Ok(())
+    }
+
     fn needs_parallel_validation(&self) -> bool {
         false
     }
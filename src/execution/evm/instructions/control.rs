@@ -9,7 +9,7 @@ pub(crate) fn ret(state: &mut ExecutionState) -> Result<(), StatusCode> {
     if let Some(region) = super::memory::get_memory_region(state, offset, size)? {
         let offset = region.offset;
         let size = region.size.get();
-        state.output_data = state.memory[offset..][..size].to_vec().into();
+        state.set_output_data(state.memory[offset..][..size].to_vec().into())?;
     }
 
     Ok(())
@@ -1,4 +1,7 @@
-use super::common::InterpreterMessage;
+use super::{
+    common::{InterpreterMessage, StatusCode},
+    instructions::memory::MAX_BUFFER_SIZE,
+};
 use arrayvec::ArrayVec;
 use bytes::{Bytes, BytesMut};
 use derive_more::{Deref, DerefMut};
@@ -11,14 +14,28 @@ pub const MAX_CONTEXT_DEPTH: usize = 1024;
 
 /// EVM stack.
 #[derive(Clone, Debug, Default, Serialize)]
-pub struct Stack(pub ArrayVec<U256, STACK_SIZE>);
+#[serde(transparent)]
+pub struct Stack(
+    pub ArrayVec<U256, STACK_SIZE>,
+    /// Largest number of items the stack has held.
+    #[cfg(feature = "instrument")]
+    #[serde(skip)]
+    usize,
+);
 
 impl Stack {
+    #[cfg(not(feature = "instrument"))]
     #[inline]
     pub const fn new() -> Self {
         Self(ArrayVec::new_const())
     }
 
+    #[cfg(feature = "instrument")]
+    #[inline]
+    pub const fn new() -> Self {
+        Self(ArrayVec::new_const(), 0)
+    }
+
     #[inline]
     const fn get_pos(&self, pos: usize) -> usize {
         self.len() - 1 - pos
@@ -30,6 +47,16 @@ impl Stack {
         unsafe { self.0.get_unchecked(pos) }
     }
 
+    /// Same as `get`, but returns `None` if there are not enough items on the stack.
+    #[inline]
+    pub fn peek(&self, pos: usize) -> Option<&U256> {
+        if pos < self.len() {
+            Some(self.get(pos))
+        } else {
+            None
+        }
+    }
+
     #[inline]
     pub fn get_mut(&mut self, pos: usize) -> &mut U256 {
         let pos = self.get_pos(pos);
@@ -49,6 +76,57 @@ impl Stack {
     #[inline]
     pub fn push(&mut self, v: U256) {
         unsafe { self.0.push_unchecked(v) }
+        #[cfg(feature = "instrument")]
+        {
+            self.1 = self.1.max(self.0.len());
+        }
+    }
+
+    /// Same as `push`, but fails with `StackOverflow` instead of writing past the end of a full stack.
+    /// For call sites not covered by the interpreter's stack requirement checks.
+    #[inline]
+    pub fn try_push(&mut self, v: U256) -> Result<(), StatusCode> {
+        if self.len() == STACK_SIZE {
+            return Err(StatusCode::StackOverflow);
+        }
+        self.push(v);
+        Ok(())
+    }
+
+    /// Checked `DUP<height>`: push a copy of the `height`-th item from the top, 1 being the top one.
+    #[inline]
+    pub fn dup(&mut self, height: usize) -> Result<(), StatusCode> {
+        let v = *height
+            .checked_sub(1)
+            .and_then(|pos| self.peek(pos))
+            .ok_or(StatusCode::StackUnderflow)?;
+        self.try_push(v)
+    }
+
+    /// Push several items at once, in order, so that the last one ends up on top.
+    /// Fails with `StackOverflow` and leaves the stack intact if they do not all fit.
+    #[inline]
+    pub fn push_slice(&mut self, vals: &[U256]) -> Result<(), StatusCode> {
+        let len = self.0.len();
+        if len + vals.len() > STACK_SIZE {
+            return Err(StatusCode::StackOverflow);
+        }
+        unsafe {
+            std::ptr::copy_nonoverlapping(vals.as_ptr(), self.0.as_mut_ptr().add(len), vals.len());
+            self.0.set_len(len + vals.len());
+        }
+        #[cfg(feature = "instrument")]
+        {
+            self.1 = self.1.max(self.0.len());
+        }
+        Ok(())
+    }
+
+    /// Largest number of items the stack has held.
+    #[cfg(feature = "instrument")]
+    #[inline]
+    pub const fn high_water(&self) -> usize {
+        self.1
     }
 
     #[inline]
@@ -94,6 +172,17 @@ impl Default for Memory {
     }
 }
 
+/// Copy of interpreter state at some point of execution, for use in step tracers.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ExecutionSnapshot {
+    pub gas_left: i64,
+    /// Stack items, from bottom to top.
+    pub stack: Vec<U256>,
+    /// Memory contents, if requested.
+    pub memory: Option<Vec<u8>>,
+    pub return_data: Bytes,
+}
+
 /// EVM execution state.
 #[derive(Clone, Debug, Getters, MutGetters)]
 pub struct ExecutionState<'m> {
@@ -104,8 +193,11 @@ pub struct ExecutionState<'m> {
     #[getset(get = "pub", get_mut = "pub")]
     pub(crate) memory: Memory,
     pub(crate) message: &'m InterpreterMessage,
+    /// Output of the last sub-call made by this frame, as seen by `RETURNDATASIZE` and `RETURNDATACOPY`.
     #[getset(get = "pub", get_mut = "pub")]
     pub(crate) return_data: Bytes,
+    /// Final output of this frame, set by `RETURN` or `REVERT`.
+    #[getset(get = "pub")]
     pub(crate) output_data: Bytes,
 }
 
@@ -120,11 +212,40 @@ impl<'m> ExecutionState<'m> {
             output_data: Bytes::new(),
         }
     }
+
+    /// Set final output of this frame. Output larger than addressable memory is rejected as out of gas.
+    pub fn set_output_data(&mut self, output_data: Bytes) -> Result<(), StatusCode> {
+        if output_data.len() as u128 > MAX_BUFFER_SIZE {
+            return Err(StatusCode::OutOfGas);
+        }
+
+        self.output_data = output_data;
+
+        Ok(())
+    }
+
+    /// Capture current state. Copying memory may be expensive, so it is only done if `with_memory` is set.
+    pub fn snapshot(&self, with_memory: bool) -> ExecutionSnapshot {
+        ExecutionSnapshot {
+            gas_left: self.gas_left,
+            stack: self.stack.0.to_vec(),
+            memory: with_memory.then(|| self.memory.to_vec()),
+            return_data: self.return_data.clone(),
+        }
+    }
+
+    /// Deepest the stack has been during execution. Stack usage costs no gas, so this is only
+    /// tracked for profiling contracts with deep stacks.
+    #[cfg(feature = "instrument")]
+    pub fn stack_high_water(&self) -> usize {
+        self.stack.high_water()
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::execution::evm::CallKind;
 
     #[test]
     fn stack() {
@@ -144,6 +265,147 @@ mod tests {
         assert_eq!(*stack.get(2), 0xde);
     }
 
+    #[test]
+    fn stack_push_slice() {
+        let mut stack = Stack::new();
+        stack.push(U256::new(0xde));
+
+        assert_eq!(
+            stack.push_slice(&[U256::new(0xad), U256::new(0xbe), U256::new(0xef)]),
+            Ok(())
+        );
+        assert_eq!(stack.len(), 4);
+        assert_eq!(*stack.get(0), 0xef);
+        assert_eq!(*stack.get(1), 0xbe);
+        assert_eq!(*stack.get(2), 0xad);
+        assert_eq!(*stack.get(3), 0xde);
+
+        assert_eq!(stack.push_slice(&[]), Ok(()));
+        assert_eq!(stack.len(), 4);
+
+        assert_eq!(stack.push_slice(&[U256::ONE; STACK_SIZE - 4]), Ok(()));
+        assert_eq!(stack.len(), STACK_SIZE);
+    }
+
+    #[test]
+    fn stack_try_push() {
+        let mut stack = Stack::new();
+        for i in 0..STACK_SIZE as u128 {
+            assert_eq!(stack.try_push(i.into()), Ok(()));
+        }
+        assert_eq!(stack.try_push(U256::ZERO), Err(StatusCode::StackOverflow));
+        assert_eq!(stack.len(), STACK_SIZE);
+        assert_eq!(*stack.get(0), STACK_SIZE as u128 - 1);
+    }
+
+    #[test]
+    fn stack_peek_dup() {
+        let mut stack = Stack::new();
+        assert_eq!(stack.peek(0), None);
+        assert_eq!(stack.dup(1), Err(StatusCode::StackUnderflow));
+
+        for i in 0..16_u128 {
+            stack.push(i.into());
+        }
+        assert_eq!(stack.peek(0), Some(&U256::from(15_u128)));
+        assert_eq!(stack.peek(15), Some(&U256::ZERO));
+        assert_eq!(stack.peek(16), None);
+
+        // DUP1
+        assert_eq!(stack.dup(1), Ok(()));
+        assert_eq!(*stack.get(0), 15_u128);
+        // DUP16
+        assert_eq!(stack.dup(16), Ok(()));
+        assert_eq!(*stack.get(0), 1_u128);
+        assert_eq!(stack.len(), 18);
+        assert_eq!(stack.dup(0), Err(StatusCode::StackUnderflow));
+        assert_eq!(stack.dup(19), Err(StatusCode::StackUnderflow));
+
+        while stack.len() < STACK_SIZE - 1 {
+            stack.push(U256::ZERO);
+        }
+        assert_eq!(stack.dup(1), Ok(()));
+        assert_eq!(stack.dup(1), Err(StatusCode::StackOverflow));
+        assert_eq!(stack.len(), STACK_SIZE);
+    }
+
+    #[test]
+    fn stack_push_slice_overflow() {
+        let mut stack = Stack::new();
+        stack.push(U256::ONE);
+        assert_eq!(
+            stack.push_slice(&[U256::ZERO; STACK_SIZE]),
+            Err(StatusCode::StackOverflow)
+        );
+        assert_eq!(stack.len(), 1);
+        assert_eq!(*stack.get(0), U256::ONE);
+    }
+
+    #[cfg(feature = "instrument")]
+    #[test]
+    fn stack_high_water() {
+        let mut stack = Stack::new();
+        assert_eq!(stack.high_water(), 0);
+
+        for item in 0..3_u128 {
+            stack.push(item.into());
+        }
+        stack.pop();
+        stack.pop();
+        stack.push(U256::ZERO);
+
+        assert_eq!(stack.len(), 2);
+        assert_eq!(stack.high_water(), 3);
+    }
+
+    fn message(gas: i64) -> InterpreterMessage {
+        InterpreterMessage {
+            kind: CallKind::Call,
+            is_static: false,
+            depth: 0,
+            gas,
+            recipient: Default::default(),
+            sender: Default::default(),
+            input_data: Default::default(),
+            value: U256::ZERO,
+            real_sender: Default::default(),
+            code_address: Default::default(),
+        }
+    }
+
+    #[test]
+    fn output_data() {
+        let message = message(1000);
+        let mut state = ExecutionState::new(&message);
+        state.return_data = Bytes::from_static(&[0xaa]);
+        assert!(state.output_data().is_empty());
+
+        state
+            .set_output_data(Bytes::from_static(&[0xde, 0xad]))
+            .unwrap();
+        assert_eq!(&state.output_data()[..], &[0xde, 0xad]);
+        assert_eq!(&state.return_data()[..], &[0xaa]);
+    }
+
+    #[test]
+    fn snapshot() {
+        let message = message(1000);
+        let mut state = ExecutionState::new(&message);
+        state.stack.push(1_u128.into());
+        state.stack.push(2_u128.into());
+        state.memory.grow(64);
+        state.memory[0] = 0xff;
+
+        let snapshot = state.snapshot(false);
+        assert_eq!(snapshot.gas_left, 1000);
+        assert_eq!(snapshot.stack, vec![U256::from(1_u128), U256::from(2_u128)]);
+        assert_eq!(snapshot.memory, None);
+
+        let memory = state.snapshot(true).memory.unwrap();
+        assert_eq!(memory.len(), 64);
+        assert_eq!(memory[0], 0xff);
+    }
+
     #[test]
     fn grow() {
         let mut mem = Memory::new();
@@ -5,6 +5,14 @@ use anyhow::Context;
 use std::{collections::HashMap, fs::DirBuilder, marker::PhantomData, ops::Deref, path::Path};
 use tables::*;
 
+/// Count operation on a table if `metrics` feature is enabled, no-op otherwise.
+macro_rules! record {
+    ($table:expr, $op:ident) => {
+        #[cfg(feature = "metrics")]
+        super::stats::record($table, super::stats::Op::$op);
+    };
+}
+
 #[derive(Clone, Debug)]
 struct TableObjectWrapper<T>(T);
 
@@ -180,6 +188,7 @@ where
     }
 
     pub fn get<T: Table>(&self, table: T, key: T::Key) -> anyhow::Result<Option<T::Value>> {
+        record!(table.db_name().as_ref(), Get);
         Ok(self
             .inner
             .get::<TableObjectWrapper<_>>(
@@ -195,6 +204,7 @@ impl<'env, E: EnvironmentKind> MdbxTransaction<'env, RW, E> {
     where
         T: Table,
     {
+        record!(table.db_name().as_ref(), Put);
         Ok(self.inner.put(
             &self.inner.open_db(Some(table.db_name().as_ref()))?,
             &k.encode(),
@@ -207,6 +217,7 @@ impl<'env, E: EnvironmentKind> MdbxTransaction<'env, RW, E> {
     where
         T: Table,
     {
+        record!(table.db_name().as_ref(), Delete);
         let mut vref = None;
         let value = value.map(TableEncode::encode);
 
@@ -279,6 +290,7 @@ where
     where
         T::Key: TableDecode,
     {
+        record!(&self.t, Seek);
         map_res_inner::<T, _>(self.inner.set_range(key.encode().as_ref()))
     }
 
@@ -286,6 +298,7 @@ where
     where
         T::Key: TableDecode,
     {
+        record!(&self.t, Seek);
         map_res_inner::<T, _>(self.inner.set_key(key.encode().as_ref()))
     }
 
@@ -326,6 +339,7 @@ where
         T: Table,
         T::Key: TableDecode,
     {
+        record!(&self.t, Walk);
         TryGenIter::from(move |_| {
             let mut fv = if let Some(start_key) = start_key {
                 self.seek(start_key)?
@@ -350,6 +364,7 @@ where
         T: Table,
         T::Key: TableDecode,
     {
+        record!(&self.t, Walk);
         TryGenIter::from(move |_| {
             let mut fv = if let Some(start_key) = start_key {
                 self.seek(start_key)?
@@ -380,6 +395,7 @@ where
     where
         T::Key: Clone,
     {
+        record!(&self.t, Seek);
         let res = self.inner.get_both_range::<TableObjectWrapper<T::Value>>(
             key.encode().as_ref(),
             value.encode().as_ref(),
@@ -439,6 +455,7 @@ where
     where
         T::Key: Clone + TableDecode,
     {
+        record!(&self.t, Walk);
         TryGenIter::from(move |_| {
             let mut v = if let Some(seek_both_key) = seek_both_key {
                 self.seek_both_range(start_key, seek_both_key)?
@@ -463,6 +480,7 @@ where
     where
         T::Key: TableDecode,
     {
+        record!(&self.t, Walk);
         TryGenIter::from(move |_| {
             if self.seek_exact(start_key)?.is_some() {
                 let mut v = self.last_dup()?;
@@ -483,6 +501,7 @@ where
     T: Table,
 {
     pub fn put(&mut self, key: T::Key, value: T::Value) -> anyhow::Result<()> {
+        record!(&self.t, Put);
         Ok(self.inner.put(
             key.encode().as_ref(),
             value.encode().as_ref(),
@@ -491,6 +510,7 @@ where
     }
 
     pub fn upsert(&mut self, key: T::Key, value: T::Value) -> anyhow::Result<()> {
+        record!(&self.t, Put);
         Ok(self.inner.put(
             key.encode().as_ref(),
             value.encode().as_ref(),
@@ -499,6 +519,7 @@ where
     }
 
     pub fn append(&mut self, key: T::Key, value: T::Value) -> anyhow::Result<()> {
+        record!(&self.t, Put);
         Ok(self.inner.put(
             key.encode().as_ref(),
             value.encode().as_ref(),
@@ -507,6 +528,7 @@ where
     }
 
     pub fn delete_current(&mut self) -> anyhow::Result<()> {
+        record!(&self.t, Delete);
         self.inner.del(WriteFlags::CURRENT)?;
 
         Ok(())
@@ -518,9 +540,11 @@ where
     T: DupSort,
 {
     pub fn delete_current_duplicates(&mut self) -> anyhow::Result<()> {
+        record!(&self.t, Delete);
         Ok(self.inner.del(WriteFlags::NO_DUP_DATA)?)
     }
     pub fn append_dup(&mut self, key: T::Key, value: T::Value) -> anyhow::Result<()> {
+        record!(&self.t, Put);
         Ok(self.inner.put(
             key.encode().as_ref(),
             value.encode().as_ref(),
@@ -1,4 +1,6 @@
 pub mod mdbx;
+#[cfg(feature = "metrics")]
+pub mod stats;
 pub mod tables;
 pub mod traits;
 
@@ -0,0 +1,120 @@
+//! Per-table counters of database operations, for finding which tables dominate I/O.
+//!
+//! Counters are process-wide and only compiled in with the `metrics` feature.
+
+use dashmap::DashMap;
+use once_cell::sync::Lazy;
+use std::{
+    collections::HashMap,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum Op {
+    Get,
+    Put,
+    Seek,
+    Walk,
+    Delete,
+}
+
+#[derive(Debug, Default)]
+struct Counters([AtomicU64; 5]);
+
+static COUNTERS: Lazy<DashMap<String, Counters>> = Lazy::new(DashMap::new);
+
+/// Operation counts for a single table.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TableStats {
+    pub gets: u64,
+    pub puts: u64,
+    /// Cursor seeks, including those starting a walk.
+    pub seeks: u64,
+    /// Number of walks started, not the number of entries walked over.
+    pub walks: u64,
+    /// Deletes by key and of cursor entries.
+    pub deletes: u64,
+}
+
+/// Snapshot of operation counts, keyed by table name.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct MdbxStats {
+    pub tables: HashMap<String, TableStats>,
+}
+
+impl MdbxStats {
+    /// Current counts for all tables touched so far.
+    pub fn snapshot() -> Self {
+        Self {
+            tables: COUNTERS
+                .iter()
+                .map(|entry| {
+                    let load = |op: Op| entry.value().0[op as usize].load(Ordering::Relaxed);
+                    (
+                        entry.key().clone(),
+                        TableStats {
+                            gets: load(Op::Get),
+                            puts: load(Op::Put),
+                            seeks: load(Op::Seek),
+                            walks: load(Op::Walk),
+                            deletes: load(Op::Delete),
+                        },
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    /// Reset all counters to zero.
+    pub fn reset() {
+        COUNTERS.clear();
+    }
+}
+
+pub(crate) fn record(table: &str, op: Op) {
+    if let Some(counters) = COUNTERS.get(table) {
+        counters.0[op as usize].fetch_add(1, Ordering::Relaxed);
+        return;
+    }
+
+    COUNTERS.entry(table.to_string()).or_default().0[op as usize].fetch_add(1, Ordering::Relaxed);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        kv::{new_mem_chaindata, tables},
+        models::BlockNumber,
+    };
+
+    #[test]
+    fn count_operations() {
+        let db = new_mem_chaindata().unwrap();
+        let tx = db.begin_mutable().unwrap();
+
+        let before = MdbxStats::snapshot()
+            .tables
+            .remove("TotalGas")
+            .unwrap_or_default();
+
+        tx.set(tables::TotalGas, BlockNumber(1), 1).unwrap();
+        tx.get(tables::TotalGas, BlockNumber(1)).unwrap();
+        tx.get(tables::TotalGas, BlockNumber(1)).unwrap();
+        let mut cursor = tx.cursor(tables::TotalGas).unwrap();
+        cursor.seek_exact(BlockNumber(1)).unwrap();
+        cursor.walk(None).for_each(drop);
+        cursor.seek_exact(BlockNumber(1)).unwrap();
+        cursor.delete_current().unwrap();
+        tx.set(tables::TotalGas, BlockNumber(2), 2).unwrap();
+        tx.del(tables::TotalGas, BlockNumber(2), None).unwrap();
+
+        let after = MdbxStats::snapshot().tables.remove("TotalGas").unwrap();
+        // Counters are shared with concurrently running tests
+        assert!(after.puts > before.puts);
+        assert!(after.gets >= before.gets + 2);
+        assert!(after.seeks > before.seeks);
+        assert!(after.walks > before.walks);
+        assert!(after.deletes >= before.deletes + 2);
+    }
+}
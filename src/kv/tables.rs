@@ -403,6 +403,23 @@ impl TableDecode for Vec<Address> {
     }
 }
 
+impl TableEncode for Vec<Withdrawal> {
+    type Encoded = Vec<u8>;
+
+    fn encode(self) -> Self::Encoded {
+        let mut v = Vec::new();
+        fastrlp::encode_list(&self, &mut v);
+        v
+    }
+}
+
+impl TableDecode for Vec<Withdrawal> {
+    fn decode(mut b: &[u8]) -> anyhow::Result<Self> {
+        <Self as fastrlp::Decodable>::decode(&mut b)
+            .map_err(|e| format_err!("failed to decode withdrawals: {e}"))
+    }
+}
+
 impl TableEncode for Vec<H256> {
     type Encoded = Vec<u8>;
 
@@ -890,6 +907,7 @@ decl_table!(Header => BlockNumber => BlockHeader);
 decl_table!(HeadersTotalDifficulty => BlockNumber => U256);
 decl_table!(BlockBody => BlockNumber => BodyForStorage);
 decl_table!(BlockTransaction => TxIndex => MessageWithSignature);
+decl_table!(BlockWithdrawals => BlockNumber => Vec<Withdrawal>);
 decl_table!(TotalGas => BlockNumber => u64);
 decl_table!(TotalTx => BlockNumber => u64);
 decl_table!(LogAddressIndex => BitmapKey<Address> => RoaringTreemap);
@@ -939,6 +957,7 @@ pub static CHAINDATA_TABLES: Lazy<Arc<DatabaseChart>> = Lazy::new(|| {
             table_entry!(HeadersTotalDifficulty),
             table_entry!(BlockBody),
             table_entry!(BlockTransaction),
+            table_entry!(BlockWithdrawals),
             table_entry!(TotalGas),
             table_entry!(TotalTx),
             table_entry!(LogAddressIndex),
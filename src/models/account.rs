@@ -131,6 +131,46 @@ impl Account {
         Ok(Some(a))
     }
 
+    /// Decode only the nonce from storage encoding.
+    pub fn decode_nonce_from_storage(mut enc: &[u8]) -> anyhow::Result<Option<u64>> {
+        if enc.is_empty() {
+            return Ok(None);
+        }
+
+        let field_set = AccountStorageFlags::from_bytes([enc.get_u8()]);
+
+        let decode_length = field_set.nonce_len().into();
+        if enc.len() < decode_length {
+            bail!("nonce is truncated")
+        }
+
+        Ok(Some(bytes_to_u64(&enc[..decode_length])))
+    }
+
+    /// Decode only the balance from storage encoding, skipping preceding fields.
+    pub fn decode_balance_from_storage(mut enc: &[u8]) -> anyhow::Result<Option<U256>> {
+        if enc.is_empty() {
+            return Ok(None);
+        }
+
+        let field_set = AccountStorageFlags::from_bytes([enc.get_u8()]);
+
+        let mut skip = usize::from(field_set.nonce_len());
+        if field_set.code_hash() {
+            skip += KECCAK_LENGTH;
+        }
+        if enc.len() < skip {
+            bail!("account is truncated")
+        }
+        enc.advance(skip);
+
+        if enc.len() > 32 {
+            bail!("balance cannot be longer than 32 bytes")
+        }
+
+        Ok(Some(U256::from_be_bytes(static_left_pad(enc))))
+    }
+
     pub fn to_rlp(&self, storage_root: H256) -> RlpAccount {
         RlpAccount {
             nonce: self.nonce,
@@ -160,6 +200,15 @@ mod tests {
             .unwrap();
 
         assert_eq!(original, decoded);
+
+        assert_eq!(
+            Account::decode_nonce_from_storage(&encoded_account).unwrap(),
+            Some(original.nonce)
+        );
+        assert_eq!(
+            Account::decode_balance_from_storage(&encoded_account).unwrap(),
+            Some(original.balance)
+        );
     }
 
     #[test]
@@ -93,6 +93,22 @@ impl From<Block> for BlockBody {
     }
 }
 
+/// Validator withdrawal from the beacon chain (EIP-4895).
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, RlpEncodable, RlpDecodable)]
+pub struct Withdrawal {
+    pub index: u64,
+    pub validator_index: u64,
+    pub address: Address,
+    /// Amount in Gwei.
+    pub amount: u64,
+}
+
+impl TrieEncode for Withdrawal {
+    fn trie_encode(&self, buf: &mut dyn bytes::BufMut) {
+        self.encode(buf)
+    }
+}
+
 #[derive(Clone, Debug, Default)]
 pub struct BlockBodyWithSenders {
     pub transactions: Vec<MessageWithSender>,
@@ -112,6 +128,9 @@ pub struct BodyForStorage {
     pub base_tx_id: TxIndex,
     pub tx_amount: u64,
     pub ommers: ArrayVec<BlockHeader, 2>,
+    /// Withdrawals of post-Shanghai blocks. Not part of the compact encoding,
+    /// they are kept in a separate table by `storage_body` accessors.
+    pub withdrawals: Option<Vec<Withdrawal>>,
 }
 
 impl BodyForStorage {
@@ -188,6 +207,7 @@ impl BodyForStorage {
             base_tx_id,
             tx_amount,
             ommers,
+            withdrawals: None,
         })
     }
 }
@@ -347,6 +347,21 @@ impl Decodable for BlockHeader {
     }
 }
 
+/// Reasonable upper bound on encoded header size, well above any legitimate header.
+pub const MAX_HEADER_RLP_LENGTH: usize = 1 << 12;
+
+/// Decode RLP-encoded header, rejecting encodings longer than `max_len` before decoding any fields.
+pub fn decode_header_bounded(buf: &mut &[u8], max_len: usize) -> Result<BlockHeader, DecodeError> {
+    let mut peek = *buf;
+    let rlp_head = Header::decode(&mut peek)?;
+    let encoded_len = buf.len() - peek.len() + rlp_head.payload_length;
+    if encoded_len > max_len {
+        return Err(DecodeError::Custom("header exceeds maximum encoded length"));
+    }
+
+    BlockHeader::decode(buf)
+}
+
 impl BlockHeader {
     #[must_use]
     pub fn new(partial_header: PartialHeader, ommers_hash: H256, transactions_root: H256) -> Self {
@@ -558,3 +573,28 @@ impl PartialHeader {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bounded_header_rlp() {
+        let v = BlockHeader {
+            number: 13_500_000.into(),
+            extra_data: vec![0xab; 1024].into(),
+            ..BlockHeader::empty()
+        };
+
+        let mut out = BytesMut::new();
+        Encodable::encode(&v, &mut out);
+
+        assert!(decode_header_bounded(&mut &*out, 1024).is_err());
+
+        let mut out = &*out;
+        let decoded = decode_header_bounded(&mut out, MAX_HEADER_RLP_LENGTH).unwrap();
+        assert!(out.is_empty());
+
+        assert_eq!(decoded, v);
+    }
+}
@@ -63,6 +63,7 @@ impl NodeBuilder {
         let forks = config.forks().into_iter().map(|f| *f).collect::<Vec<_>>();
 
         let (chain_tip_sender, chain_tip) = watch::channel(Default::default());
+        let (fork_choice_sender, fork_choice) = watch::channel(Default::default());
 
         Ok(Node {
             stash,
@@ -71,9 +72,14 @@ impl NodeBuilder {
             config,
             chain_tip,
             chain_tip_sender,
+            fork_choice,
+            fork_choice_sender,
             bad_blocks: Default::default(),
+            peer_scores: Default::default(),
+            pending_penalties: Default::default(),
             block_cache: Mutex::new(LruCache::new(64)),
             block_cache_notify: Notify::new(),
+            td_claims: Mutex::new(LruCache::new(64)),
             forks,
         })
     }
@@ -2,11 +2,12 @@
 
 use super::{stash::Stash, stream::*};
 use crate::{
-    models::{BlockNumber, ChainConfig, MessageWithSignature, H256},
+    consensus::ExternalForkChoice,
+    models::{BlockNumber, ChainConfig, MessageWithSignature, H256, U256},
     p2p::types::*,
 };
 use bytes::{BufMut, BytesMut};
-use dashmap::DashSet;
+use dashmap::{DashMap, DashSet};
 use ethereum_interfaces::{
     sentry as grpc_sentry,
     sentry::{sentry_client::SentryClient, PeerMinBlockRequest, SentPeers},
@@ -18,9 +19,9 @@ use hashlink::LruCache;
 use parking_lot::{Mutex, RwLock};
 use rand::{thread_rng, Rng};
 use std::{
-    collections::HashSet,
+    collections::{HashMap, HashSet},
     future::{pending, Future},
-    sync::Arc,
+    sync::{atomic::AtomicUsize, Arc},
     time::Duration,
 };
 use task_group::TaskGroup;
@@ -48,17 +49,31 @@ pub struct Node {
     /// Highest persistent chain tip.
     pub chain_tip: watch::Receiver<(BlockNumber, H256)>,
     pub chain_tip_sender: watch::Sender<(BlockNumber, H256)>,
+    /// Fork choice set by an external consensus layer driver.
+    pub fork_choice: watch::Receiver<ExternalForkChoice>,
+    pub fork_choice_sender: watch::Sender<ExternalForkChoice>,
     /// Block cache
     pub block_cache: Mutex<LruCache<H256, (SentryId, PeerId, crate::models::Block)>>,
     pub block_cache_notify: Notify,
+    /// Total difficulties peers announced in their status or along with new blocks, checked against the computed ones.
+    /// Each peer's claim for a block is kept separately.
+    pub td_claims: Mutex<LruCache<H256, HashMap<(SentryId, PeerId), U256>>>,
     /// Table of block hashes of the blocks known to not belong to the canonical chain.
     pub bad_blocks: DashSet<H256>,
+    /// Response statistics of peers, used to route requests to the most reliable ones.
+    pub peer_scores: DashMap<(SentryId, PeerId), PeerScore>,
+    /// Peer penalties queued by stages but not yet sent to sentries.
+    pub pending_penalties: AtomicUsize,
     /// Chain forks.
     pub forks: Vec<u64>,
 }
 
 impl Node {
     const SYNC_INTERVAL: Duration = Duration::from_secs(5);
+    /// Number of best peers header requests are spread over.
+    const BEST_PEERS: usize = 8;
+    /// Probability of sending a header request to all peers instead of the best ones.
+    const PROBE_PROBABILITY: f64 = 0.1;
 
     /// Start node synchronization.
     pub async fn start_sync(self: Arc<Self>, tip_discovery: bool) -> anyhow::Result<()> {
@@ -70,6 +85,7 @@ impl Node {
         tasks.spawn({
             let handler = self.clone();
             let requested = requested.clone();
+            let tx = tx.clone();
 
             async move {
                 let mut stream = handler.sync_stream().await;
@@ -79,6 +95,14 @@ impl Node {
                         let sentry_id = msg.sentry_id;
 
                         match msg.msg {
+                            Message::Status(ref status) => {
+                                handler.record_td_claim(
+                                    status.best_hash,
+                                    sentry_id,
+                                    peer_id,
+                                    status.total_difficulty,
+                                );
+                            }
                             Message::NewBlockHashes(ref blocks) => {
                                 let mut max_block = None;
                                 for b in &blocks.0 {
@@ -119,14 +143,21 @@ impl Node {
                                         .await;
                                 }
 
-                                if tip_discovery
-                                    && requested.lock().remove(&headers.request_id).is_some()
+                                if requested.lock().remove(&headers.request_id).is_some()
                                     && headers.headers.len() == 1
                                 {
                                     let header = &headers.headers[0];
                                     let hash = header.hash();
+                                    let fork_choice_head = handler.fork_choice.borrow().head_block;
 
-                                    if header.number > handler.chain_tip.borrow().0 {
+                                    if hash == fork_choice_head {
+                                        // Head chosen by the consensus layer overrides peers' tips
+                                        let _ =
+                                            handler.chain_tip_sender.send((header.number, hash));
+                                    } else if tip_discovery
+                                        && fork_choice_head.is_zero()
+                                        && header.number > handler.chain_tip.borrow().0
+                                    {
                                         let _ =
                                             handler.chain_tip_sender.send((header.number, hash));
                                         for skip in 1..4_u64 {
@@ -141,13 +172,25 @@ impl Node {
                                 let hash = inner.block.header.hash();
                                 let number = inner.block.header.number;
 
+                                handler.record_td_claim(
+                                    hash,
+                                    sentry_id,
+                                    peer_id,
+                                    U256::from(inner.total_difficulty),
+                                );
                                 handler
                                     .block_cache
                                     .lock()
                                     .insert(hash, (sentry_id, peer_id, inner.block));
                                 handler.block_cache_notify.notify_one();
 
-                                if tip_discovery && number > handler.chain_tip.borrow().0 {
+                                let fork_choice_head = handler.fork_choice.borrow().head_block;
+                                if hash == fork_choice_head {
+                                    let _ = handler.chain_tip_sender.send((number, hash));
+                                } else if tip_discovery
+                                    && fork_choice_head.is_zero()
+                                    && number > handler.chain_tip.borrow().0
+                                {
                                     let _ = handler.chain_tip_sender.send((number, hash));
                                     for skip in 1..4_u64 {
                                         let id = rand::thread_rng().gen::<u64>();
@@ -164,6 +207,32 @@ impl Node {
             }
         });
 
+        tasks.spawn({
+            let handler = self.clone();
+            let requested = requested.clone();
+            let mut fork_choice = self.fork_choice.clone();
+
+            async move {
+                // Look up the number of each new fork choice head, the response moves chain tip to it
+                while fork_choice.changed().await.is_ok() {
+                    let head = fork_choice.borrow().head_block;
+                    if head.is_zero() {
+                        continue;
+                    }
+
+                    if let Some(number) = handler.forkchoice_head_number() {
+                        let _ = handler.chain_tip_sender.send((number, head));
+                    } else {
+                        let id = rand::thread_rng().gen::<u64>();
+                        requested.lock().insert(id, ());
+                        tx.send((id, PeerFilter::All, head, 0u64)).await?;
+                    }
+                }
+
+                Ok::<_, anyhow::Error>(())
+            }
+        });
+
         let _ = tasks.spawn({
             let handler = self.clone();
 
@@ -299,6 +368,44 @@ impl Node {
         self.set_status(status_data).await
     }
 
+    /// Records fork choice of the consensus layer and makes the headers stage download towards `head`.
+    /// `finalized` bounds how deep the headers stage may unwind.
+    pub fn set_forkchoice(&self, head: H256, safe: H256, finalized: H256) {
+        let _ = self.fork_choice_sender.send(ExternalForkChoice {
+            head_block: head,
+            safe_block: safe,
+            finalized_block: finalized,
+        });
+    }
+
+    /// Remember total difficulty of block `hash` announced by a peer, next to other peers' claims for it.
+    pub fn record_td_claim(&self, hash: H256, sentry_id: SentryId, peer_id: PeerId, td: U256) {
+        let mut claims = self.td_claims.lock();
+        if let Some(claims) = claims.get_mut(&hash) {
+            claims.insert((sentry_id, peer_id), td);
+        } else {
+            claims.insert(hash, HashMap::from([((sentry_id, peer_id), td)]));
+        }
+    }
+
+    /// Number of the fork choice head, if the head is set and its header has been seen.
+    pub fn forkchoice_head_number(&self) -> Option<BlockNumber> {
+        let head = self.fork_choice.borrow().head_block;
+        if head.is_zero() {
+            return None;
+        }
+
+        let (tip_number, tip_hash) = *self.chain_tip.borrow();
+        if tip_hash == head {
+            return Some(tip_number);
+        }
+
+        self.block_cache
+            .lock()
+            .get(&head)
+            .map(|(_, _, block)| block.header.number)
+    }
+
     pub async fn send_message(
         &self,
         msg: Message,
@@ -316,20 +423,48 @@ impl Node {
             .await
     }
 
+    /// Send header requests, preferring the best scored peers. Returns each request along with
+    /// the peers it was routed to.
     pub async fn send_many_header_requests<T>(
         self: Arc<Self>,
         requests: T,
-    ) -> HashSet<(SentryId, PeerId)>
+    ) -> Vec<(HeaderRequest, HashSet<(SentryId, PeerId)>)>
     where
         T: IntoIterator<Item = HeaderRequest>,
     {
+        let best_peers = self.best_peers(Self::BEST_PEERS);
+
         requests
             .into_iter()
-            .map(|request| {
+            .enumerate()
+            .map(|(i, request)| {
                 let node = self.clone();
+                let peer =
+                    if best_peers.is_empty() || thread_rng().gen_bool(Self::PROBE_PROBABILITY) {
+                        None
+                    } else {
+                        Some(best_peers[i % best_peers.len()])
+                    };
                 tokio::spawn(async move {
                     trace!("Sending header request: {request:?}");
-                    node.send_header_request(None, request, None).await
+                    if let Some((sentry_id, peer_id)) = peer {
+                        let sent = node
+                            .send_message(
+                                Message::GetBlockHeaders(GetBlockHeaders {
+                                    request_id: rand::thread_rng().gen::<u64>(),
+                                    params: request.into(),
+                                }),
+                                PeerFilter::Peer(peer_id, sentry_id),
+                            )
+                            .await;
+                        if !sent.is_empty() {
+                            return (request, sent);
+                        }
+
+                        // Peer is gone, forget about it
+                        node.peer_scores.remove(&(sentry_id, peer_id));
+                    }
+                    (request, node.send_header_request(None, request, None).await)
                 })
             })
             .collect::<FuturesUnordered<_>>()
@@ -337,7 +472,6 @@ impl Node {
             .await
             .into_iter()
             .flat_map(|res| res.ok())
-            .flatten()
             .collect()
     }
 
@@ -460,10 +594,11 @@ impl Node {
         self.send_raw(data, pred).await
     }
 
-    const SYNC_PREDICATE: [i32; 3] = [
+    const SYNC_PREDICATE: [i32; 4] = [
         grpc_sentry::MessageId::BlockHeaders66 as i32,
         grpc_sentry::MessageId::NewBlockHashes66 as i32,
         grpc_sentry::MessageId::NewBlock66 as i32,
+        grpc_sentry::MessageId::Status66 as i32,
     ];
     async fn sync_stream(&self) -> NodeStream {
         self.update_chain_head(None).await;
@@ -547,6 +682,37 @@ impl Node {
         sum
     }
 
+    pub fn record_peer_success(&self, sentry_id: SentryId, peer_id: PeerId, latency: Duration) {
+        self.peer_scores
+            .entry((sentry_id, peer_id))
+            .or_default()
+            .record_success(latency);
+    }
+
+    pub fn record_peer_failure(&self, sentry_id: SentryId, peer_id: PeerId) {
+        self.peer_scores
+            .entry((sentry_id, peer_id))
+            .or_default()
+            .record_failure();
+    }
+
+    /// Returns up to `n` peers with the highest score, best first.
+    pub fn best_peers(&self, n: usize) -> Vec<(SentryId, PeerId)> {
+        let mut peers = self
+            .peer_scores
+            .iter()
+            .map(|entry| (*entry.key(), entry.value().value()))
+            .collect::<Vec<_>>();
+        peers.sort_unstable_by(|(_, a), (_, b)| b.total_cmp(a));
+
+        peers.into_iter().take(n).map(|(peer, _)| peer).collect()
+    }
+
+    pub async fn penalize(&self, penalty: Penalty) {
+        debug!("Penalizing peer {} for {:?}", penalty.peer_id, penalty.kind);
+        self.penalize_peer(penalty.peer_id).await
+    }
+
     pub async fn penalize_peer(&self, peer_id: impl Into<ethereum_interfaces::types::H512>) {
         let request = grpc_sentry::PenalizePeerRequest {
             peer_id: Some(peer_id.into()),
@@ -43,6 +43,7 @@ where
         let mut headers = Vec::with_capacity(limit as usize);
         let mut number_cursor = txn.cursor(tables::HeaderNumber)?;
         let mut header_cursor = txn.cursor(tables::Header)?;
+        let mut canonical_cursor = txn.cursor(tables::CanonicalHeader)?;
 
         let mut next_number = match params.start {
             BlockId::Hash(hash) => number_cursor.seek_exact(hash)?.map(|(_, k)| k),
@@ -52,8 +53,11 @@ where
         for _ in 0..limit {
             match next_number {
                 Some(block_number) => {
-                    if let Some((_, header)) = header_cursor.seek_exact(block_number)? {
-                        headers.push(header);
+                    // Headers kept by a soft unwind have no canonical entry and are not served
+                    if canonical_cursor.seek_exact(block_number)?.is_some() {
+                        if let Some((_, header)) = header_cursor.seek_exact(block_number)? {
+                            headers.push(header);
+                        }
                     }
                     next_number = u64::try_from(block_number.0 as i64 + add_op)
                         .ok()
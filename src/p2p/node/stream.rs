@@ -42,8 +42,9 @@ impl SentryStream {
                 .into_inner();
 
             Box::pin(async_stream::stream! {
-                loop {
-                    if let Some(Ok(msg)) = inner_stream.next().await {
+                // Ends when the sentry closes the stream
+                while let Some(msg) = inner_stream.next().await {
+                    if let Ok(msg) = msg {
                         let peer_id = msg.peer_id.clone();
 
                         if let Ok(msg) = InboundMessage::new(msg, sentry_id) {
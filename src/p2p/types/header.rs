@@ -1,5 +1,5 @@
 use crate::{
-    models::{BlockHeader, BlockNumber, H256},
+    models::{decode_header_bounded, BlockHeader, BlockNumber, H256, MAX_HEADER_RLP_LENGTH},
     p2p::types::BlockId,
 };
 use fastrlp::*;
@@ -54,8 +54,40 @@ impl From<HeaderRequest> for GetBlockHeadersParams {
     }
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, RlpEncodable, RlpDecodable)]
+#[derive(Debug, Clone, PartialEq, Eq, RlpEncodable)]
 pub struct BlockHeaders {
     pub request_id: u64,
     pub headers: Vec<BlockHeader>,
 }
+
+impl Decodable for BlockHeaders {
+    fn decode(buf: &mut &[u8]) -> Result<Self, DecodeError> {
+        let rlp_head = Header::decode(buf)?;
+        if !rlp_head.list {
+            return Err(DecodeError::UnexpectedString);
+        }
+        let started_len = buf.len();
+        let request_id = Decodable::decode(buf)?;
+
+        let headers_head = Header::decode(buf)?;
+        if !headers_head.list {
+            return Err(DecodeError::UnexpectedString);
+        }
+        let leftover = buf.len() - headers_head.payload_length;
+        let mut headers = Vec::new();
+        while buf.len() > leftover {
+            headers.push(decode_header_bounded(buf, MAX_HEADER_RLP_LENGTH)?);
+        }
+        if buf.len() != leftover || started_len - buf.len() != rlp_head.payload_length {
+            return Err(DecodeError::ListLengthMismatch {
+                expected: rlp_head.payload_length,
+                got: started_len - buf.len(),
+            });
+        }
+
+        Ok(Self {
+            request_id,
+            headers,
+        })
+    }
+}
@@ -1,7 +1,7 @@
 use crate::{
     models::{BlockBody, MessageWithSignature, H256},
     p2p::types::*,
-    sentry::devp2p::PeerId,
+    sentry::{devp2p::PeerId, eth::StatusMessage},
 };
 use anyhow::anyhow;
 use ethereum_interfaces::sentry as grpc_sentry;
@@ -119,6 +119,7 @@ pub struct BlockBodies {
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub enum Message {
+    Status(StatusMessage),
     NewBlockHashes(NewBlockHashes),
     GetBlockHeaders(GetBlockHeaders),
     GetBlockBodies(GetBlockBodies),
@@ -135,6 +136,7 @@ impl Message {
     #[inline(always)]
     pub const fn id(&self) -> MessageId {
         match self {
+            Self::Status(_) => MessageId::Status,
             Self::NewBlockHashes(_) => MessageId::NewBlockHashes,
             Self::GetBlockHeaders(_) => MessageId::GetBlockHeaders,
             Self::GetBlockBodies(_) => MessageId::GetBlockBodies,
@@ -189,6 +191,7 @@ impl InboundMessage {
             Some(msg_id) => msg_id,
             _ => return Err(anyhow!("Unsupported message id: {}", value.id)),
         })? {
+            MessageId::Status => Message::Status(Decodable::decode(msg_data_slice)?),
             MessageId::NewBlockHashes => {
                 Message::NewBlockHashes(Decodable::decode(msg_data_slice)?)
             }
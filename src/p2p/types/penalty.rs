@@ -1,5 +1,6 @@
 use crate::sentry::devp2p::PeerId;
 use ethereum_interfaces::sentry as grpc_sentry;
+use std::time::Duration;
 
 #[derive(Debug, Clone, Default)]
 pub enum PenaltyKind {
@@ -11,6 +12,8 @@ pub enum PenaltyKind {
     InvalidSeal,
     TooFarFuture,
     TooFarPast,
+    /// Announced total difficulty disagrees with the one computed from the served headers.
+    TdMismatch,
 }
 
 #[derive(Debug, Clone)]
@@ -28,3 +31,59 @@ impl From<Penalty> for grpc_sentry::PenalizePeerRequest {
         }
     }
 }
+
+/// Response statistics of a peer.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct PeerScore {
+    pub successes: u64,
+    pub failures: u64,
+    /// Moving average of response latency.
+    pub latency: Duration,
+}
+
+impl PeerScore {
+    const LATENCY_SMOOTHING: u32 = 8;
+
+    pub fn record_success(&mut self, latency: Duration) {
+        self.latency = if self.successes == 0 {
+            latency
+        } else {
+            (self.latency * (Self::LATENCY_SMOOTHING - 1) + latency) / Self::LATENCY_SMOOTHING
+        };
+        self.successes += 1;
+    }
+
+    pub fn record_failure(&mut self) {
+        self.failures += 1;
+    }
+
+    /// Smoothed success rate, discounted by average latency in seconds.
+    pub fn value(&self) -> f64 {
+        let success_rate =
+            (self.successes + 1) as f64 / (self.successes + self.failures + 2) as f64;
+        success_rate / (1.0 + self.latency.as_secs_f64())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn peer_score() {
+        let mut fast = PeerScore::default();
+        let mut slow = PeerScore::default();
+        let mut unreliable = PeerScore::default();
+        for _ in 0..10 {
+            fast.record_success(Duration::from_millis(100));
+            slow.record_success(Duration::from_secs(3));
+            unreliable.record_success(Duration::from_millis(100));
+            unreliable.record_failure();
+        }
+
+        assert_eq!(fast.latency, Duration::from_millis(100));
+        assert!(fast.value() > unreliable.value());
+        assert!(fast.value() > slow.value());
+        assert!(PeerScore::default().value() > slow.value());
+    }
+}
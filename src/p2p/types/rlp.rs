@@ -26,6 +26,7 @@ impl Encodable for BlockId {
 impl Encodable for Message {
     fn encode(&self, out: &mut dyn BufMut) {
         match *self {
+            Message::Status(ref value) => value.encode(out),
             Message::NewBlockHashes(ref value) => value.encode(out),
             Message::GetBlockHeaders(ref value) => value.encode(out),
             Message::GetBlockBodies(ref value) => value.encode(out),
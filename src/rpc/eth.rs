@@ -439,13 +439,11 @@ where
         tokio::task::spawn_blocking(move || {
             let txn = db.begin()?;
 
-            Ok(state::account::read(
+            Ok(state::account::read_balance(
                 &txn,
                 address,
                 Some(helpers::resolve_block_number(&txn, block_number)?),
-            )?
-            .map(|acc| acc.balance)
-            .unwrap_or(U256::ZERO))
+            )?)
         })
         .await
         .unwrap_or_else(helpers::joinerror_to_result)
@@ -572,15 +570,9 @@ where
             let txn = db.begin()?;
             let block_number = helpers::resolve_block_number(&txn, block_number)?;
             Ok(
-                if let Some(account) = state::account::read(&txn, address, Some(block_number))? {
-                    txn.get(tables::Code, account.code_hash)?
-                        .ok_or_else(|| {
-                            format_err!("failed to find code for code hash {}", account.code_hash)
-                        })?
-                        .into()
-                } else {
-                    Default::default()
-                },
+                state::code::read_for_account(&txn, address, Some(block_number))?
+                    .unwrap_or_default()
+                    .into(),
             )
         })
         .await
@@ -665,13 +657,11 @@ where
 
         tokio::task::spawn_blocking(move || {
             let txn = db.begin()?;
-            Ok(state::account::read(
+            Ok(state::account::read_nonce(
                 &txn,
                 address,
                 Some(helpers::resolve_block_number(&txn, block_number)?),
             )?
-            .map(|account| account.nonce)
-            .unwrap_or(0)
             .into())
         })
         .await
@@ -869,10 +859,11 @@ where
         tokio::task::spawn_blocking(move || {
             let txn = db.begin()?;
 
-            let highest_block = stages::HEADERS
-                .get_progress(&txn)?
-                .unwrap_or(BlockNumber(0));
-            let current_block = stages::FINISH.get_progress(&txn)?.unwrap_or(BlockNumber(0));
+            let crate::stagedsync::SyncStatus {
+                current_block,
+                highest_block,
+                ..
+            } = crate::stagedsync::sync_status(&txn, &[stages::HEADERS, FINISH], None)?;
 
             Ok(if current_block > 0 && current_block >= highest_block {
                 // Sync completed
@@ -267,6 +267,13 @@ impl CapabilityServerImpl {
                                     ethereum_interfaces::sentry::peer_event::PeerEventId::Connect
                                         as i32,
                             });
+
+                            // Node checks the announced total difficulty against served headers
+                            let _ = self.data_sender.send(InboundMessage {
+                                id: sentry::MessageId::Status66 as i32,
+                                data,
+                                peer_id: Some(peer.into()),
+                            });
                         }
                     }
                     Some(inbound_id) if valid_peer => {
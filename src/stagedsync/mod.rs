@@ -2,7 +2,13 @@ pub mod stage;
 pub mod util;
 
 use self::stage::{Stage, StageInput, UnwindInput};
-use crate::{kv::mdbx::*, models::*, stagedsync::stage::*, StageId};
+use crate::{
+    kv::{mdbx::*, tables},
+    models::*,
+    stagedsync::stage::*,
+    StageId,
+};
+use anyhow::{bail, format_err};
 use futures::future::BoxFuture;
 use std::time::{Duration, Instant};
 use tokio::sync::watch::{Receiver as WatchReceiver, Sender as WatchSender};
@@ -24,6 +30,110 @@ pub struct StageExecutionReceipt {
     pub duration: Duration,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SyncStatus {
+    /// Block up to which all specified stages are complete.
+    pub current_block: BlockNumber,
+    /// Best known block, either the chain tip or the furthest stage progress.
+    pub highest_block: BlockNumber,
+    pub stages: Vec<(StageId, BlockNumber)>,
+}
+
+/// Aggregate progress of specified stages.
+///
+/// `chain_tip` is the best block known to the network layer (see [crate::p2p::node::Node::chain_tip]), if any.
+pub fn sync_status<K, E>(
+    tx: &MdbxTransaction<'_, K, E>,
+    stage_ids: &[StageId],
+    chain_tip: Option<BlockNumber>,
+) -> anyhow::Result<SyncStatus>
+where
+    K: TransactionKind,
+    E: EnvironmentKind,
+{
+    let stages = stage_progress(tx, stage_ids)?;
+
+    let current_block = lowest_progress(&stages);
+    let highest_block = stages
+        .iter()
+        .map(|(_, progress)| *progress)
+        .chain(chain_tip)
+        .max()
+        .unwrap_or_default();
+
+    Ok(SyncStatus {
+        current_block,
+        highest_block,
+        stages,
+    })
+}
+
+/// Block up to which all specified stages are complete. Stages which have not recorded progress
+/// yet count as being at genesis.
+///
+/// While the node is syncing this is the latest block fully available to fork choice and RPC,
+/// as opposed to headers progress.
+pub fn lowest_stage_progress<K, E>(
+    tx: &MdbxTransaction<'_, K, E>,
+    stage_ids: &[StageId],
+) -> anyhow::Result<BlockNumber>
+where
+    K: TransactionKind,
+    E: EnvironmentKind,
+{
+    Ok(lowest_progress(&stage_progress(tx, stage_ids)?))
+}
+
+fn stage_progress<K, E>(
+    tx: &MdbxTransaction<'_, K, E>,
+    stage_ids: &[StageId],
+) -> anyhow::Result<Vec<(StageId, BlockNumber)>>
+where
+    K: TransactionKind,
+    E: EnvironmentKind,
+{
+    stage_ids
+        .iter()
+        .map(|stage_id| Ok((*stage_id, stage_id.get_progress(tx)?.unwrap_or_default())))
+        .collect()
+}
+
+fn lowest_progress(stages: &[(StageId, BlockNumber)]) -> BlockNumber {
+    stages
+        .iter()
+        .map(|(_, progress)| *progress)
+        .min()
+        .unwrap_or_default()
+}
+
+/// Set recorded progress of a stage, so that it runs again from `to` on the next sync cycle.
+/// Returns previous progress.
+///
+/// Data the stage has already written above `to` is left in place, so this suits stages that
+/// overwrite their output when re-executed. Unwind other stages instead.
+pub fn reset_stage_progress<E>(
+    tx: &MdbxTransaction<'_, RW, E>,
+    stage_id: StageId,
+    to: BlockNumber,
+) -> anyhow::Result<Option<BlockNumber>>
+where
+    E: EnvironmentKind,
+{
+    let tip = tx
+        .cursor(tables::CanonicalHeader)?
+        .last()?
+        .map(|(number, _)| number)
+        .ok_or_else(|| format_err!("no canonical chain"))?;
+    if to > tip {
+        bail!("cannot reset stage {stage_id} to #{to}, above chain tip #{tip}");
+    }
+
+    let previous = stage_id.get_progress(tx)?;
+    stage_id.save_progress(tx, to)?;
+
+    Ok(previous)
+}
+
 #[derive(Clone, Debug, PartialEq, Eq)]
 pub struct StagedSyncStatus {
     pub maximum_progress: Option<BlockNumber>,
@@ -355,8 +465,8 @@ where
                                         );
                                     }
                                 }
-                                ExecOutput::Unwind { unwind_to } => {
-                                    info!(to = unwind_to.0, "Unwind requested");
+                                ExecOutput::Unwind { unwind_to, reason } => {
+                                    info!(to = unwind_to.0, "Unwind requested: {reason}");
                                 }
                             }
 
@@ -418,7 +528,7 @@ where
 
                                 restarted = true
                             }
-                            Ok(stage::ExecOutput::Unwind { unwind_to: to }) => {
+                            Ok(stage::ExecOutput::Unwind { unwind_to: to, .. }) => {
                                 // Stage has asked us to unwind.
                                 // Set unwind point and restart the whole staged sync loop.
                                 // Current DB transaction will be aborted.
@@ -504,3 +614,93 @@ pub fn format_duration(dur: Duration, subsec_millis: bool) -> String {
         }
     )
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::kv::new_mem_chaindata;
+
+    #[test]
+    fn sync_status() {
+        let db = new_mem_chaindata().unwrap();
+        let tx = db.begin_mutable().unwrap();
+
+        const STAGE_A: StageId = StageId("A");
+        const STAGE_B: StageId = StageId("B");
+        const STAGE_C: StageId = StageId("C");
+
+        STAGE_A.save_progress(&tx, BlockNumber(100)).unwrap();
+        STAGE_B.save_progress(&tx, BlockNumber(50)).unwrap();
+
+        assert_eq!(
+            super::sync_status(&tx, &[STAGE_A, STAGE_B], None).unwrap(),
+            SyncStatus {
+                current_block: BlockNumber(50),
+                highest_block: BlockNumber(100),
+                stages: vec![(STAGE_A, BlockNumber(100)), (STAGE_B, BlockNumber(50))],
+            }
+        );
+
+        let status = super::sync_status(&tx, &[STAGE_A, STAGE_C], Some(BlockNumber(200))).unwrap();
+        assert_eq!(status.current_block, BlockNumber(0));
+        assert_eq!(status.highest_block, BlockNumber(200));
+    }
+
+    #[test]
+    fn lowest_stage_progress() {
+        let db = new_mem_chaindata().unwrap();
+        let tx = db.begin_mutable().unwrap();
+
+        const STAGE_A: StageId = StageId("A");
+        const STAGE_B: StageId = StageId("B");
+        const STAGE_C: StageId = StageId("C");
+        const STAGE_D: StageId = StageId("D");
+        let stages = [STAGE_A, STAGE_B, STAGE_C];
+
+        assert_eq!(
+            super::lowest_stage_progress(&tx, &stages).unwrap(),
+            BlockNumber(0)
+        );
+
+        // First sync, only some stages have run
+        STAGE_A.save_progress(&tx, BlockNumber(100)).unwrap();
+        assert_eq!(
+            super::lowest_stage_progress(&tx, &stages).unwrap(),
+            BlockNumber(0)
+        );
+
+        STAGE_B.save_progress(&tx, BlockNumber(50)).unwrap();
+        STAGE_C.save_progress(&tx, BlockNumber(75)).unwrap();
+        // Stale progress of a stage no longer run
+        STAGE_D.save_progress(&tx, BlockNumber(10)).unwrap();
+        assert_eq!(
+            super::lowest_stage_progress(&tx, &stages).unwrap(),
+            BlockNumber(50)
+        );
+    }
+
+    #[test]
+    fn reset_stage_progress() {
+        let db = new_mem_chaindata().unwrap();
+        let tx = db.begin_mutable().unwrap();
+
+        const STAGE: StageId = StageId("A");
+
+        assert!(super::reset_stage_progress(&tx, STAGE, BlockNumber(0)).is_err());
+
+        for number in 0..=10 {
+            tx.set(tables::CanonicalHeader, BlockNumber(number), H256::zero())
+                .unwrap();
+        }
+        STAGE.save_progress(&tx, BlockNumber(10)).unwrap();
+
+        assert!(super::reset_stage_progress(&tx, STAGE, BlockNumber(11)).is_err());
+        assert_eq!(STAGE.get_progress(&tx).unwrap(), Some(BlockNumber(10)));
+
+        assert_eq!(
+            super::reset_stage_progress(&tx, STAGE, BlockNumber(4)).unwrap(),
+            Some(BlockNumber(10))
+        );
+        assert_eq!(STAGE.get_progress(&tx).unwrap(), Some(BlockNumber(4)));
+    }
+}
@@ -26,10 +26,50 @@ pub struct UnwindInput {
     pub bad_block: Option<BlockNumber>,
 }
 
+/// Why a stage requested an unwind.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UnwindReason {
+    /// Parent hash of the block does not match the hash of the stored block below it.
+    ParentMismatch {
+        block: BlockNumber,
+        expected: H256,
+        found: H256,
+    },
+    /// Chain received from the network does not link to the stored block.
+    DoesNotAttach { block: BlockNumber, hash: H256 },
+    /// Clique signer sealed the block too soon after its previous one, which happens during reorgs.
+    CliqueSignedRecently { block: BlockNumber, signer: Address },
+}
+
+impl Display for UnwindReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::ParentMismatch {
+                block,
+                expected,
+                found,
+            } => write!(
+                f,
+                "parent hash of block #{block} is {found:?}, expected {expected:?}"
+            ),
+            Self::DoesNotAttach { block, hash } => {
+                write!(
+                    f,
+                    "received chain does not attach to block #{block} ({hash:?})"
+                )
+            }
+            Self::CliqueSignedRecently { block, signer } => {
+                write!(f, "signer {signer:?} of block #{block} signed recently")
+            }
+        }
+    }
+}
+
 #[derive(Debug, PartialEq, Eq)]
 pub enum ExecOutput {
     Unwind {
         unwind_to: BlockNumber,
+        reason: UnwindReason,
     },
     Progress {
         stage_progress: BlockNumber,
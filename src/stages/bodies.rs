@@ -1,9 +1,10 @@
 use crate::{
+    accessors,
     consensus::{Consensus, DuoError},
     kv::{mdbx::MdbxTransaction, tables, traits::ttw},
     models::*,
     p2p::{
-        node::{Node, NodeStream},
+        node::{Node, NodeStream, PeerId, SentryId},
         types::{BlockBodies, Message},
     },
     stagedsync::stage::*,
@@ -43,6 +44,8 @@ pub struct BodyDownload {
     pub node: Arc<Node>,
     /// Consensus engine used.
     pub consensus: Arc<dyn Consensus>,
+    /// Bodies with larger RLP encoding are discarded and requested again.
+    pub max_body_rlp_bytes: usize,
 }
 
 enum DownloadError {
@@ -125,7 +128,6 @@ where
         'db: 'tx,
     {
         let mut block_body_cur = txn.cursor(tables::BlockBody)?;
-        let mut block_tx_cur = txn.cursor(tables::BlockTransaction)?;
 
         while let Some((number, body)) = block_body_cur.last()? {
             if number <= input.unwind_to {
@@ -133,15 +135,8 @@ where
             }
 
             block_body_cur.delete_current()?;
-            let mut deleted = 0;
-            while deleted < body.tx_amount {
-                let to_delete = body.base_tx_id + deleted;
-                if block_tx_cur.seek_exact(to_delete)?.is_some() {
-                    block_tx_cur.delete_current()?;
-                }
-
-                deleted += 1;
-            }
+            txn.del(tables::BlockWithdrawals, number, None)?;
+            accessors::chain::tx::delete_range(txn, body.base_tx_id, body.tx_amount)?;
         }
 
         Ok(UnwindOutput {
@@ -365,7 +360,7 @@ impl BodyDownload {
                 let mut pending_bodies = Vec::with_capacity(batch_size);
 
                 let s = stream.filter_map(|msg| match msg.msg {
-                    Message::BlockBodies(msg) => Some(msg),
+                    Message::BlockBodies(bodies) => Some(((msg.sentry_id, msg.peer_id), bodies)),
                     _ => None,
                 });
                 tokio::pin!(s);
@@ -379,11 +374,11 @@ impl BodyDownload {
                 loop {
                     select! {
                         res = s.next() => {
-                            if let Some(BlockBodies { request_id, bodies }) = res {
+                            if let Some((peer, BlockBodies { request_id, bodies })) = res {
                                 let mut pending_responses = session.pending_responses.lock();
                                 pending_responses.remove(request_id);
                                 debug!("Accepted block bodies with id {request_id}");
-                                pending_bodies.push(bodies);
+                                pending_bodies.push((peer, bodies));
 
                                 if pending_responses.count() == 0 {
                                     break;
@@ -393,25 +388,21 @@ impl BodyDownload {
                             }
                         }
                         _ = &mut notified, if will_reach_tip => {
-                            let cached_blocks: Vec<BlockBody> = session.handler.block_cache.lock().drain().map(|(
+                            pending_bodies.extend(session.handler.block_cache.lock().drain().map(|(
                                 _,
                                 (
-                                    _,
-                                    _,
+                                    sentry_id,
+                                    peer_id,
                                     Block {
                                         transactions,
                                         ommers,
                                         ..
                                     },
                                 ),
-                            )| BlockBody {
+                            )| ((sentry_id, peer_id), vec![BlockBody {
                                 transactions,
                                 ommers,
-                            }).collect();
-
-                            if !cached_blocks.is_empty() {
-                                pending_bodies.push(cached_blocks);
-                            }
+                            }])));
                             break;
                         }
 
@@ -422,16 +413,32 @@ impl BodyDownload {
                 }
 
                 let mut received = 0;
+                let mut oversized_from = HashSet::<(SentryId, PeerId)>::new();
                 if !pending_bodies.is_empty() {
                     let tmp = pending_bodies
                         .par_drain(..)
-                        .flatten()
-                        .map(|body| ((body.ommers_hash(), body.transactions_root()), body))
+                        .flat_map_iter(|(peer, bodies)| {
+                            bodies.into_iter().map(move |body| (peer, body))
+                        })
+                        .map(|(peer, body)| {
+                            ((body.ommers_hash(), body.transactions_root()), (peer, body))
+                        })
                         .collect::<Vec<_>>();
 
                     let mut requests = session.requests.write();
-                    for (key, value) in tmp {
+                    for (key, (peer, value)) in tmp {
                         if let Some((number, hash)) = requests.remove(&key) {
+                            if let Err(e) = accessors::chain::storage_body::check_bounded(
+                                hash,
+                                number,
+                                &value,
+                                self.max_body_rlp_bytes,
+                            ) {
+                                warn!("Discarding {e}, penalizing peer {:?}", peer.1);
+                                requests.insert(key, (number, hash));
+                                oversized_from.insert(peer);
+                                continue;
+                            }
                             bodies.insert(number, (hash, value));
                             received += 1;
                         } else {
@@ -440,6 +447,11 @@ impl BodyDownload {
                     }
                 }
 
+                for (sentry_id, peer_id) in oversized_from {
+                    session.handler.record_peer_failure(sentry_id, peer_id);
+                    session.handler.penalize_peer(peer_id).await;
+                }
+
                 total_received += received;
                 let elapsed = started_at.elapsed().as_secs();
                 stats.push_back((total_received, elapsed));
@@ -520,6 +532,7 @@ impl BodyDownload {
                     base_tx_id: TxIndex(base_tx_id),
                     tx_amount: block.transactions.len() as u64,
                     ommers: block.ommers,
+                    withdrawals: None,
                 },
             )?;
 
@@ -230,7 +230,7 @@ where
                 for cause in e.chain() {
                     if let Some(DuoError::Validation(ValidationError::CliqueError(
                         CliqueError::SignedRecently {
-                            signer: _,
+                            signer,
                             current,
                             last: _,
                             limit,
@@ -242,7 +242,13 @@ where
                             "Trying to recover from {} by unwinding to {}",
                             &cause, unwind_to
                         );
-                        return Ok(ExecOutput::Unwind { unwind_to });
+                        return Ok(ExecOutput::Unwind {
+                            unwind_to,
+                            reason: UnwindReason::CliqueSignedRecently {
+                                block: *current,
+                                signer: *signer,
+                            },
+                        });
                     }
                 }
             }
@@ -2,40 +2,51 @@
 
 use crate::{
     accessors,
-    consensus::{fork_choice_graph::ForkChoiceGraph, Consensus, ForkChoiceMode},
+    consensus::{
+        fork_choice_graph::ForkChoiceGraph, Consensus, DuoError, ForkChoiceMode, ValidationError,
+    },
     kv::{mdbx::*, tables},
-    models::{BlockHeader, BlockNumber, H256},
+    models::{BlockHeader, BlockNumber, H256, U256},
     p2p::{
-        node::{Node, NodeStream},
-        types::{BlockHeaders, BlockId, HeaderRequest, Message, Status},
+        node::{Node, NodeStream, SentryId},
+        types::{BlockHeaders, BlockId, HeaderRequest, Message, Penalty, PenaltyKind, Status},
     },
     stagedsync::{stage::*, util::unwind_by_block_key},
     StageId, TaskGuard,
 };
-use anyhow::format_err;
+use anyhow::{bail, format_err};
 use async_trait::async_trait;
-use dashmap::DashMap;
+use dashmap::{DashMap, DashSet};
 use ethereum_types::H512;
+use hashlink::LruCache;
 use parking_lot::Mutex;
 use rand::prelude::*;
 use rayon::iter::{IndexedParallelIterator, IntoParallelRefIterator, ParallelIterator};
 use std::{
-    collections::BTreeMap,
+    collections::{BTreeMap, HashMap, HashSet},
     convert::identity,
+    fmt::Debug,
+    future::Future,
     sync::{
         atomic::{AtomicUsize, Ordering},
         Arc,
     },
     time::Duration,
 };
-use tokio::time::Instant;
-use tokio_stream::StreamExt;
+use tokio::{
+    sync::{mpsc, Semaphore},
+    time::Instant,
+};
+use tokio_stream::{Stream, StreamExt};
+use tokio_util::sync::CancellationToken;
 use tracing::*;
 
 const HEADERS_UPPER_BOUND: usize = 1 << 10;
 
 const STAGE_UPPER_BOUND: BlockNumber = BlockNumber(90_000);
 const REQUEST_INTERVAL: Duration = Duration::from_secs(10);
+/// Claimed total difficulty may differ from the computed one by this fraction of it.
+const TD_TOLERANCE_DIVISOR: u128 = 1000;
 
 pub const HEADERS: StageId = StageId("Headers");
 
@@ -43,8 +54,224 @@ pub const HEADERS: StageId = StageId("Headers");
 pub struct HeaderDownload {
     pub node: Arc<Node>,
     pub consensus: Arc<dyn Consensus>,
+    /// Headers above it are not written. Those already downloaded are kept in `orphans`, if set,
+    /// so raising it, or passing the pool to a new downloader with a higher limit, does not download them again.
     pub max_block: BlockNumber,
     pub increment: Option<BlockNumber>,
+    /// Limits concurrent header request bursts, may be shared between several downloaders.
+    /// Unbounded if `None`.
+    pub request_limit: Option<Arc<Semaphore>>,
+    /// Whether to write `HeaderNumber` index along with headers.
+    /// If disabled, it should be built later with [accessors::chain::build_header_number_index].
+    pub build_header_number: bool,
+    /// Keeps header segments received ahead of the requested range for later linking.
+    /// Such headers are dropped if `None`.
+    pub orphans: Option<Arc<OrphanPool>>,
+    /// Number of tasks penalizing misbehaving peers concurrently.
+    pub penalize_workers: usize,
+    /// Time limit for one download session, after which headers received so far are written
+    /// and the stage is re-entered. Unlimited if `None`.
+    pub per_call_deadline: Option<Duration>,
+    /// Minimum time before an unanswered request is sent again. Outstanding requests are checked
+    /// every few seconds, and all of them are rebroadcast on each check if `None`.
+    pub retry_interval: Option<Duration>,
+    /// Source of the chain tip to download towards. Chain tip seen by `node` if `None`.
+    pub chain_tip: Option<Arc<dyn ChainTipSource>>,
+    /// Known hashes of some blocks. Downloaded headers at these heights must match them.
+    pub checkpoints: BTreeMap<BlockNumber, H256>,
+    /// Whether to collect and report reasons for all headers failing seal validation in a batch,
+    /// instead of only stopping at the lowest one.
+    pub collect_seal_failures: bool,
+    /// Whether to write total difficulty along with headers. If disabled, `HeadersTotalDifficulty`
+    /// lags behind `Header` and `CanonicalHeader` until filled with [accessors::chain::td::repair_range],
+    /// which must be done before anything reading total difficulty runs, including this stage with it enabled,
+    /// which otherwise fails.
+    pub compute_td: bool,
+    /// Unwinds at most this many blocks deep only remove canonical pointers and keep `Header` and
+    /// total difficulty entries. If the unwound chain wins again, only the target header is downloaded
+    /// on top of the kept ones, which are then made canonical. `Header` holds one header per block
+    /// number, so kept headers are lost once a competing chain is written over them.
+    /// All unwinds delete headers if `None`.
+    pub soft_unwind_depth: Option<u64>,
+}
+
+/// Provides the current tip of the chain for forward header download.
+#[async_trait]
+pub trait ChainTipSource: Debug + Send + Sync {
+    /// Latest known chain tip.
+    fn current(&self) -> (BlockNumber, H256);
+
+    /// Wait until chain tip is above `number` and return it.
+    async fn wait_above(&self, number: BlockNumber) -> (BlockNumber, H256);
+}
+
+#[async_trait]
+impl ChainTipSource for Node {
+    fn current(&self) -> (BlockNumber, H256) {
+        *self.chain_tip.borrow()
+    }
+
+    async fn wait_above(&self, number: BlockNumber) -> (BlockNumber, H256) {
+        let mut chain_tip = self.chain_tip.clone();
+        loop {
+            let tip = *chain_tip.borrow_and_update();
+            if tip.0 > number {
+                return tip;
+            }
+            let _ = chain_tip.changed().await;
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum SealValidationError {
+    /// Headers from `first_invalid` on are not to be used.
+    Invalid {
+        first_invalid: usize,
+        invalid_hash: H256,
+    },
+    /// Validation was abandoned, its result is partial.
+    Cancelled,
+}
+
+/// Header which failed seal validation, along with the reason.
+type SealFailure = (BlockNumber, H256, DuoError);
+
+/// Bounded pool of contiguous header segments keyed by parent hash of the first header.
+/// The oldest segment is evicted when the pool is full.
+#[derive(Debug)]
+pub struct OrphanPool {
+    segments: Mutex<LruCache<H256, Vec<(H256, BlockHeader)>>>,
+}
+
+impl OrphanPool {
+    pub fn new(max_orphans: usize) -> Self {
+        Self {
+            segments: Mutex::new(LruCache::new(max_orphans)),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.segments.lock().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.segments.lock().is_empty()
+    }
+
+    fn insert(&self, segment: Vec<(H256, BlockHeader)>) {
+        if let Some((_, first)) = segment.first() {
+            self.segments.lock().insert(first.parent_hash, segment);
+        }
+    }
+
+    /// Move segments that attach to `anchor` or to headers in the graph into the graph.
+    /// Returns numbers of promoted headers.
+    fn promote(&self, graph: &mut ForkChoiceGraph, anchor: H256) -> HashSet<BlockNumber> {
+        let mut segments = self.segments.lock();
+        let mut promoted = HashSet::new();
+        loop {
+            let attached = segments
+                .iter()
+                .map(|(parent_hash, _)| *parent_hash)
+                .filter(|parent_hash| *parent_hash == anchor || graph.contains(parent_hash))
+                .collect::<Vec<_>>();
+            if attached.is_empty() {
+                break;
+            }
+
+            for parent_hash in attached {
+                if let Some(segment) = segments.remove(&parent_hash) {
+                    for (hash, header) in segment {
+                        let number = header.number;
+                        if !graph.insert_with_hash(hash, header) {
+                            break;
+                        }
+                        promoted.insert(number);
+                    }
+                }
+            }
+        }
+
+        promoted
+    }
+}
+
+/// Penalizes peers in background tasks, at most once per peer over the queue's lifetime.
+/// Workers exit once the queue is dropped and all queued penalties are applied.
+/// Queued penalties are also counted in `Node::pending_penalties`.
+#[derive(Debug)]
+struct PenaltyQueue {
+    node: Arc<Node>,
+    sender: mpsc::UnboundedSender<Penalty>,
+    penalized: DashSet<H512>,
+    pending: Arc<AtomicUsize>,
+}
+
+impl PenaltyQueue {
+    fn new(node: Arc<Node>, workers: usize) -> Self {
+        Self::with_penalizer(node.clone(), workers, move |penalty| {
+            let node = node.clone();
+            async move { node.penalize(penalty).await }
+        })
+    }
+
+    fn with_penalizer<F, Fut>(node: Arc<Node>, workers: usize, penalizer: F) -> Self
+    where
+        F: Fn(Penalty) -> Fut + Clone + Send + 'static,
+        Fut: Future<Output = ()> + Send,
+    {
+        let (sender, receiver) = mpsc::unbounded_channel::<Penalty>();
+        let receiver = Arc::new(tokio::sync::Mutex::new(receiver));
+        let pending = Arc::new(AtomicUsize::new(0));
+
+        for _ in 0..workers.max(1) {
+            tokio::spawn({
+                let node = node.clone();
+                let receiver = receiver.clone();
+                let pending = pending.clone();
+                let penalizer = penalizer.clone();
+
+                async move {
+                    loop {
+                        // Release the receiver before penalizing so that other workers can proceed
+                        let next = { receiver.lock().await.recv().await };
+                        let Some(penalty) = next else { break };
+                        let peer_id = penalty.peer_id;
+
+                        penalizer(penalty).await;
+                        node.pending_penalties.fetch_sub(1, Ordering::SeqCst);
+                        let pending = pending.fetch_sub(1, Ordering::SeqCst) - 1;
+                        debug!("Penalized peer {peer_id}, {pending} penalties pending");
+                    }
+                }
+            });
+        }
+
+        Self {
+            node,
+            sender,
+            penalized: DashSet::new(),
+            pending,
+        }
+    }
+
+    fn penalize(&self, peer_id: H512, kind: PenaltyKind) {
+        if self.penalized.insert(peer_id) {
+            let pending = self.pending.fetch_add(1, Ordering::SeqCst) + 1;
+            self.node.pending_penalties.fetch_add(1, Ordering::SeqCst);
+            if self.sender.send(Penalty { peer_id, kind }).is_err() {
+                self.pending.fetch_sub(1, Ordering::SeqCst);
+                self.node.pending_penalties.fetch_sub(1, Ordering::SeqCst);
+            } else {
+                debug!("Queued penalty for peer {peer_id}, {pending} penalties pending");
+            }
+        }
+    }
+
+    fn pending(&self) -> usize {
+        self.pending.load(Ordering::SeqCst)
+    }
 }
 
 #[async_trait]
@@ -98,10 +325,17 @@ where
 
                     info!("Awaiting chain tip from external consensus engine...");
 
+                    let mut node_fork_choice = self.node.fork_choice.clone();
                     let (chain_tip_hash, chain_finalized_hash) = loop {
-                        let _ = chain_tip_watch.changed().await;
-
-                        let fork_choice = *chain_tip_watch.borrow();
+                        let fork_choice = tokio::select! {
+                            Ok(()) = chain_tip_watch.changed() => *chain_tip_watch.borrow(),
+                            Ok(()) = node_fork_choice.changed() => *node_fork_choice.borrow(),
+                            else => {
+                                return Err(StageError::Internal(format_err!(
+                                    "fork choice channels closed"
+                                )))
+                            }
+                        };
                         if !fork_choice.head_block.is_zero()
                             && fork_choice.head_block != prev_progress_hash
                         {
@@ -135,6 +369,10 @@ where
                                     .checked_sub(1)
                                     .ok_or_else(|| format_err!("Attempting to reorg past genesis"))?
                                     .into(),
+                                reason: UnwindReason::DoesNotAttach {
+                                    block: prev_progress,
+                                    hash: prev_progress_hash,
+                                },
                             })
                         }
                         LinearDownloadResult::NoResponse => {
@@ -148,52 +386,44 @@ where
                 }
                 ForkChoiceMode::Difficulty(fork_choice_graph) => {
                     // Forward download mode
-                    let mut chain_tip = self.node.chain_tip.clone();
-                    let current_chain_tip = loop {
-                        let _ = chain_tip.changed().await;
-                        let (n, _) = *chain_tip.borrow();
-                        if n > prev_progress {
-                            break n;
-                        }
-                    };
-
-                    debug!("Chain tip={}", current_chain_tip);
-
-                    let (mut target_block, mut reached_tip) = Self::forward_set_target_block(
-                        prev_progress,
-                        self.increment,
-                        current_chain_tip,
-                    );
+                    let (mut target_block, mut reached_tip) = self.next_target(prev_progress).await;
 
                     let starting_block: BlockNumber = prev_progress + 1;
 
-                    if target_block >= self.max_block {
-                        target_block = self.max_block;
-                        reached_tip = true;
-                    }
-
                     info!(
                         "Target block for download: {target_block}{}",
                         if reached_tip { ", will reach tip" } else { "" }
                     );
 
-                    let headers_cap = (target_block.0 - starting_block.0 + 1) as usize;
+                    let mut headers_cap = (target_block.0 - starting_block.0 + 1) as usize;
                     let mut headers = Vec::<(H256, BlockHeader)>::with_capacity(headers_cap);
 
+                    // Reuse headers left by an interrupted run, download sessions continue on top of them
+                    headers.extend(Self::stored_headers_above(
+                        txn,
+                        &prev_progress_header,
+                        target_block,
+                    )?);
+                    if self.soft_unwind_depth.is_some() {
+                        // Headers kept by a soft unwind are checked by the target header attaching to them,
+                        // so it is always downloaded
+                        let (_, anchor) = Self::session_anchor(&prev_progress_header, &headers);
+                        headers.extend(Self::kept_headers_below(txn, &anchor, target_block)?);
+                    }
+                    if !headers.is_empty() {
+                        info!("Reusing {} headers already in database", headers.len());
+                    }
+
                     while headers.len() < headers_cap {
-                        let starting_block = if let Some((_, last_buffered_header)) = headers.last()
-                        {
-                            last_buffered_header.number + 1
-                        } else {
-                            starting_block
-                        };
+                        let (starting_block, anchor) =
+                            Self::session_anchor(&prev_progress_header, &headers);
 
                         info!("Download session {starting_block} to {target_block}");
 
-                        if let Some(mut downloaded) = self
+                        if let Some((mut downloaded, timed_out, lowered_tip)) = self
                             .download_headers(
                                 fork_choice_graph.clone(),
-                                &prev_progress_header,
+                                &anchor,
                                 starting_block,
                                 target_block,
                             )
@@ -207,18 +437,46 @@ where
                                         headers.pop();
                                         continue;
                                     }
-                                } else if prev_progress_hash != first_downloaded.parent_hash {
+                                } else if let Some(reason) = Self::parent_mismatch(
+                                    prev_progress,
+                                    prev_progress_hash,
+                                    first_downloaded,
+                                ) {
                                     // Does not attach to chain in database, unwind and start over
                                     return Ok(ExecOutput::Unwind {
                                         unwind_to: BlockNumber(prev_progress.saturating_sub(1)),
+                                        reason,
                                     });
                                 }
                             }
 
                             headers.append(&mut downloaded);
+
+                            if let Some(tip) = lowered_tip {
+                                if tip < target_block {
+                                    // Peers cannot serve headers above the new tip, stop the run at it
+                                    target_block = tip;
+                                    headers_cap = tip.0.saturating_sub(prev_progress.0) as usize;
+                                    headers.truncate(headers_cap);
+                                    reached_tip = true;
+                                }
+                            }
+
+                            if timed_out {
+                                // Save partial batch, the rest is downloaded when the stage is re-entered
+                                reached_tip = false;
+                                break;
+                            }
+                        } else if headers.pop().is_some() {
+                            // Does not attach to buffered chain, retry from one header below
+                            continue;
                         } else {
                             return Ok(ExecOutput::Unwind {
                                 unwind_to: BlockNumber(prev_progress.saturating_sub(1)),
+                                reason: UnwindReason::DoesNotAttach {
+                                    block: prev_progress,
+                                    hash: prev_progress_hash,
+                                },
                             });
                         }
                     }
@@ -231,30 +489,7 @@ where
                 }
             };
 
-            let mut cursor_header_number = txn.cursor(tables::HeaderNumber)?;
-            let mut cursor_header = txn.cursor(tables::Header)?;
-            let mut cursor_canonical = txn.cursor(tables::CanonicalHeader)?;
-            let mut cursor_td = txn.cursor(tables::HeadersTotalDifficulty)?;
-            let mut td = cursor_td.last()?.map(|(_, v)| v).unwrap();
-
-            for (hash, header) in headers {
-                if header.number == 0 {
-                    continue;
-                }
-                if header.number > self.max_block {
-                    break;
-                }
-
-                let block_number = header.number;
-                td += header.difficulty;
-
-                cursor_header_number.put(hash, block_number)?;
-                cursor_header.append(block_number, header)?;
-                cursor_canonical.append(block_number, hash)?;
-                cursor_td.append(block_number, td)?;
-
-                stage_progress = block_number;
-            }
+            stage_progress = self.write_headers(txn, prev_progress, headers)?;
         }
 
         Ok(ExecOutput::Progress {
@@ -276,6 +511,25 @@ where
             graph.lock().clear();
         }
 
+        // Finalized block may come from the node or straight from the engine API
+        let mut finalized_hashes = vec![self.node.fork_choice.borrow().finalized_block];
+        if let ForkChoiceMode::External(fork_choice) = self.consensus.fork_choice_mode() {
+            finalized_hashes.push(fork_choice.borrow().finalized_block);
+        }
+        for finalized in finalized_hashes {
+            if finalized.is_zero() {
+                continue;
+            }
+            if let Some(finalized_number) = tx.get(tables::HeaderNumber, finalized)? {
+                if input.unwind_to < finalized_number {
+                    bail!(
+                        "Attempting to unwind to #{} past finalized block #{finalized_number}",
+                        input.unwind_to
+                    );
+                }
+            }
+        }
+
         if let Some(bad_block) = input.bad_block {
             if let Some(hash) = tx.get(tables::CanonicalHeader, bad_block)? {
                 self.node.mark_bad_block(hash);
@@ -289,9 +543,27 @@ where
             tx.del(tables::HeaderNumber, hash, None)?;
         }
 
-        unwind_by_block_key(tx, tables::Header, input, identity)?;
+        let soft = input.bad_block.is_none()
+            && self.soft_unwind_depth.map_or(false, |depth| {
+                input.stage_progress.saturating_sub(*input.unwind_to) <= depth
+            });
+        if let (true, Some(depth)) = (soft, self.soft_unwind_depth) {
+            debug!(
+                "Keeping up to {depth} headers above #{} for re-canonicalization",
+                input.unwind_to
+            );
+            // Headers kept by earlier soft unwinds may lie further up
+            let input = UnwindInput {
+                unwind_to: input.unwind_to + depth,
+                ..input
+            };
+            unwind_by_block_key(tx, tables::Header, input, identity)?;
+            unwind_by_block_key(tx, tables::HeadersTotalDifficulty, input, identity)?;
+        } else {
+            unwind_by_block_key(tx, tables::Header, input, identity)?;
+            unwind_by_block_key(tx, tables::HeadersTotalDifficulty, input, identity)?;
+        }
         unwind_by_block_key(tx, tables::CanonicalHeader, input, identity)?;
-        unwind_by_block_key(tx, tables::HeadersTotalDifficulty, input, identity)?;
 
         Ok(UnwindOutput {
             stage_progress: input.unwind_to,
@@ -458,13 +730,17 @@ impl HeaderDownload {
         }
     }
 
+    /// Download headers `start..=end` on top of `prev_progress_header`.
+    /// Returns validated headers along with whether `per_call_deadline` cut the download short
+    /// and the chain tip if it moved down below `end`, or `None` if they do not attach.
+    /// Fails if the header stream ends, e.g. on sentry disconnect, before all requests are served.
     pub async fn download_headers(
         &self,
         fork_choice_graph: Arc<Mutex<ForkChoiceGraph>>,
         prev_progress_header: &BlockHeader,
         start: BlockNumber,
         end: BlockNumber,
-    ) -> anyhow::Result<Option<Vec<(H256, BlockHeader)>>> {
+    ) -> anyhow::Result<Option<(Vec<(H256, BlockHeader)>, bool, Option<BlockNumber>)>> {
         let requests = Arc::new(Self::prepare_requests(start, end));
         let peer_map = Arc::new(DashMap::new());
 
@@ -474,8 +750,28 @@ impl HeaderDownload {
             requests.len()
         );
 
+        if let Some(orphans) = &self.orphans {
+            let promoted =
+                orphans.promote(&mut fork_choice_graph.lock(), prev_progress_header.hash());
+            if !promoted.is_empty() {
+                debug!("Promoted {} orphaned headers", promoted.len());
+
+                // Do not request what we already have
+                requests.retain(|&number, request| {
+                    !(number..number + request.limit).all(|n| promoted.contains(&n))
+                });
+            }
+        }
+
+        let penalties = Arc::new(PenaltyQueue::new(self.node.clone(), self.penalize_workers));
         let mut stream = self.node.stream_headers().await;
+        let sent_at = Arc::new(DashMap::new());
         let is_bounded = |block_number: BlockNumber| block_number >= start && block_number <= end;
+        let deadline = self
+            .per_call_deadline
+            .map(|deadline| Instant::now() + deadline);
+        let mut timed_out = false;
+        let mut lowered_tip = None;
 
         {
             let mut tasks = Vec::new();
@@ -483,47 +779,112 @@ impl HeaderDownload {
             let _g = TaskGuard(tokio::task::spawn({
                 let node = self.node.clone();
                 let requests = requests.clone();
+                let request_limit = self.request_limit.clone();
+                let sent_at = sent_at.clone();
+                let retry_interval = self.retry_interval.unwrap_or_default();
 
                 async move {
+                    let mut last_sent = HashMap::new();
                     loop {
-                        let reqs = requests
-                            .iter()
-                            .map(|entry_ref| *entry_ref.value())
-                            .collect::<Vec<_>>();
-                        node.clone().send_many_header_requests(reqs).await;
+                        let reqs = Self::due_requests(
+                            &requests,
+                            &mut last_sent,
+                            retry_interval,
+                            Instant::now(),
+                        );
+                        if !reqs.is_empty() {
+                            let _permit = if let Some(request_limit) = &request_limit {
+                                request_limit.acquire().await.ok()
+                            } else {
+                                None
+                            };
+                            let now = Instant::now();
+                            let routed = node.clone().send_many_header_requests(reqs).await;
+                            Self::record_sent(&sent_at, now, routed);
+                        }
                         tokio::time::sleep(Self::BACK_OFF).await;
                     }
                 }
             }));
 
             while !requests.is_empty() {
-                if let Some(msg) = stream.next().await {
-                    let peer_id = msg.peer_id;
+                let (tip, _) = self.chain_tip_source().current();
+                if tip < end {
+                    lowered_tip = Some(lowered_tip.unwrap_or(tip).min(tip));
+                    if tip < start {
+                        info!("Chain tip moved down to #{tip}, below download start #{start}");
+                        return Ok(Some((vec![], false, lowered_tip)));
+                    }
 
-                    if let Message::BlockHeaders(inner) = msg.msg {
-                        if inner.headers.is_empty() {
-                            continue;
-                        }
+                    let dropped = Self::drop_requests_above(&requests, tip);
+                    if dropped > 0 {
+                        info!(
+                            "Chain tip moved down to #{tip}, dropped {dropped} requests above it"
+                        );
+                    }
+                    if requests.is_empty() {
+                        break;
+                    }
+                }
 
+                let msg = match Self::next_before(&mut stream, deadline).await {
+                    Some(Some(msg)) => msg,
+                    Some(None) => {
+                        bail!("Header stream ended with {} requests left", requests.len())
+                    }
+                    None => {
                         info!(
-                            "Received {} headers from peer {peer_id}",
-                            inner.headers.len()
+                            "Download deadline reached with {} requests left",
+                            requests.len()
                         );
+                        timed_out = true;
+                        break;
+                    }
+                };
 
-                        if is_bounded(inner.headers[0].number) {
-                            tasks.push(TaskGuard(tokio::task::spawn({
-                                let node = self.node.clone();
-                                let requests = requests.clone();
-                                let graph = fork_choice_graph.clone();
-                                let peer_map = peer_map.clone();
-
-                                async move {
-                                    Self::handle_response(
-                                        node, requests, graph, peer_map, peer_id, inner,
-                                    )
-                                    .await
-                                }
-                            })));
+                let peer_id = msg.peer_id;
+                let sentry_id = msg.sentry_id;
+
+                if let Message::BlockHeaders(inner) = msg.msg {
+                    if inner.headers.is_empty() {
+                        continue;
+                    }
+
+                    info!(
+                        "Received {} headers from peer {peer_id}",
+                        inner.headers.len()
+                    );
+
+                    if is_bounded(inner.headers[0].number) {
+                        tasks.push(TaskGuard(tokio::task::spawn({
+                            let node = self.node.clone();
+                            let requests = requests.clone();
+                            let graph = fork_choice_graph.clone();
+                            let peer_map = peer_map.clone();
+                            let penalties = penalties.clone();
+                            let latency = sent_at
+                                .remove(&(inner.headers[0].number, sentry_id, peer_id))
+                                .map(|(_, sent)| sent.elapsed());
+
+                            async move {
+                                Self::handle_response(
+                                    node,
+                                    requests,
+                                    graph,
+                                    peer_map,
+                                    &penalties,
+                                    (sentry_id, peer_id),
+                                    latency,
+                                    inner,
+                                )
+                                .await
+                            }
+                        })));
+                    } else if inner.headers[0].number > end {
+                        if let Some(orphans) = &self.orphans {
+                            if let Ok(segment) = Self::check_contiguous(inner.headers) {
+                                orphans.insert(segment);
+                            }
                         }
                     }
                 }
@@ -533,7 +894,16 @@ impl HeaderDownload {
         let took = Instant::now();
         let mut headers = {
             let mut graph = fork_choice_graph.lock();
-            let tail = if let Some(v) = graph.chain_head() {
+            if let Some(orphans) = &self.orphans {
+                orphans.promote(&mut graph, prev_progress_header.hash());
+            }
+            let tail = if timed_out {
+                // Only the part attached to our chain is usable
+                match graph.chain_head_from(prev_progress_header.hash()) {
+                    Some(v) => v,
+                    None => return Ok(Some((vec![], true, lowered_tip))),
+                }
+            } else if let Some(v) = graph.chain_head() {
                 v
             } else {
                 info!("Difficulty graph failure, will unwind");
@@ -557,26 +927,75 @@ impl HeaderDownload {
         let cur_size = headers.len();
         let took = Instant::now();
 
+        if let Err((last_valid, invalid_hash)) = self.verify_checkpoints(&headers) {
+            headers.truncate(last_valid);
+
+            if let Some((sentry_id, peer_id)) = peer_map.get(&invalid_hash).map(|e| *e) {
+                self.node.record_peer_failure(sentry_id, peer_id);
+                penalties.penalize(peer_id, PenaltyKind::BadBlock);
+            }
+        }
+
         if let Err((last_valid, invalid_hash)) =
             self.validate_sequentially(prev_progress_header, &headers)
         {
             headers.truncate(last_valid);
 
-            if let Some(peer_id) = peer_map.get(&invalid_hash).map(|e| *e) {
-                self.node.penalize_peer(peer_id).await;
+            if let Some((sentry_id, peer_id)) = peer_map.get(&invalid_hash).map(|e| *e) {
+                self.node.record_peer_failure(sentry_id, peer_id);
+                penalties.penalize(peer_id, PenaltyKind::BadBlock);
             }
         }
 
         if self.consensus.needs_parallel_validation() {
-            if let Err((last_valid, invalid_hash)) = self.validate_parallel(&headers) {
-                headers.truncate(last_valid);
+            // Validation is abandoned if this future is dropped
+            let cancel = CancellationToken::new();
+            let _cancel_on_drop = cancel.clone().drop_guard();
+            let consensus = self.consensus.clone();
+            let failures = self.collect_seal_failures.then(|| Mutex::new(Vec::new()));
+            let (res, validated, failures) = tokio::task::spawn_blocking(move || {
+                let res =
+                    Self::validate_parallel(&*consensus, &headers, &cancel, failures.as_ref());
+                (res, headers, failures.map(Mutex::into_inner))
+            })
+            .await?;
+            headers = validated;
+
+            if let Some(mut failures) = failures {
+                failures.sort_unstable_by_key(|(number, _, _)| *number);
+                for (number, hash, error) in failures {
+                    warn!("Header #{number} ({hash:?}) failed seal validation: {error}");
+                    if let Some((sentry_id, peer_id)) = peer_map.get(&hash).map(|e| *e) {
+                        self.node.record_peer_failure(sentry_id, peer_id);
+                        penalties.penalize(peer_id, PenaltyKind::InvalidSeal);
+                    }
+                }
+            }
 
-                if let Some(peer_id) = peer_map.get(&invalid_hash).map(|e| *e) {
-                    self.node.penalize_peer(peer_id).await;
+            match res {
+                Ok(()) => {}
+                Err(SealValidationError::Invalid {
+                    first_invalid,
+                    invalid_hash,
+                }) => {
+                    headers.truncate(first_invalid);
+
+                    if let Some((sentry_id, peer_id)) = peer_map.get(&invalid_hash).map(|e| *e) {
+                        self.node.record_peer_failure(sentry_id, peer_id);
+                        penalties.penalize(peer_id, PenaltyKind::InvalidSeal);
+                    }
                 }
+                Err(SealValidationError::Cancelled) => bail!("Header validation cancelled"),
             }
         }
 
+        if penalties.pending() > 0 {
+            debug!(
+                "{} peer penalties still pending, applying in background",
+                penalties.pending()
+            );
+        }
+
         if cur_size == headers.len() {
             info!(
                 "Seal verification took={:?} all headers are valid.",
@@ -590,15 +1009,29 @@ impl HeaderDownload {
             );
         }
 
-        Ok(Some(headers))
+        Ok(Some((headers, timed_out, lowered_tip)))
+    }
+
+    /// Next item of `stream`, or `None` if `deadline` passes first.
+    async fn next_before<S>(stream: &mut S, deadline: Option<Instant>) -> Option<Option<S::Item>>
+    where
+        S: Stream + Unpin,
+    {
+        if let Some(deadline) = deadline {
+            tokio::time::timeout_at(deadline, stream.next()).await.ok()
+        } else {
+            Some(stream.next().await)
+        }
     }
 
     async fn handle_response(
         node: Arc<Node>,
         requests: Arc<DashMap<BlockNumber, HeaderRequest>>,
         graph: Arc<Mutex<ForkChoiceGraph>>,
-        peer_map: Arc<DashMap<H256, H512>>,
-        peer_id: H512,
+        peer_map: Arc<DashMap<H256, (SentryId, H512)>>,
+        penalties: &PenaltyQueue,
+        (sentry_id, peer_id): (SentryId, H512),
+        latency: Option<Duration>,
         response: BlockHeaders,
     ) {
         let cur_size = response.headers.len();
@@ -617,23 +1050,202 @@ impl HeaderDownload {
                     if headers.len() == limit {
                         entry.remove();
 
-                        for (hash, header) in headers {
-                            graph.insert_with_hash(hash, header);
-                            peer_map.insert(hash, peer_id);
-                        }
+                        Self::insert_segment(
+                            &node,
+                            &mut graph,
+                            &peer_map,
+                            penalties,
+                            (sentry_id, peer_id),
+                            latency,
+                            headers,
+                        );
                     }
                 } else if !graph.contains(last_hash) {
-                    for (hash, header) in headers {
-                        graph.insert_with_hash(hash, header);
-                        peer_map.insert(hash, peer_id);
-                    }
+                    Self::insert_segment(
+                        &node,
+                        &mut graph,
+                        &peer_map,
+                        penalties,
+                        (sentry_id, peer_id),
+                        latency,
+                        headers,
+                    );
                 }
             }
             Err(()) => {
                 warn!("Rejected discontiguous header segment from {peer_id}");
-                node.penalize_peer(peer_id).await
+                node.record_peer_failure(sentry_id, peer_id);
+                penalties.penalize(peer_id, PenaltyKind::WrongChildBlockHeight);
+            }
+        }
+    }
+
+    /// Insert contiguous segment into the graph, penalizing the peer if it links to a parent at the wrong height
+    /// or conflicts with a different header under the same hash.
+    fn insert_segment(
+        node: &Node,
+        graph: &mut ForkChoiceGraph,
+        peer_map: &DashMap<H256, (SentryId, H512)>,
+        penalties: &PenaltyQueue,
+        (sentry_id, peer_id): (SentryId, H512),
+        latency: Option<Duration>,
+        headers: Vec<(H256, BlockHeader)>,
+    ) {
+        for (hash, header) in headers {
+            if !graph.insert_with_hash(hash, header) {
+                warn!("Rejected header segment from {peer_id} conflicting with the graph");
+                node.record_peer_failure(sentry_id, peer_id);
+                penalties.penalize(peer_id, PenaltyKind::BadBlock);
+                return;
+            }
+            peer_map.insert(hash, (sentry_id, peer_id));
+        }
+        // Responses to requests routed elsewhere say nothing about this peer's latency
+        if let Some(latency) = latency {
+            node.record_peer_success(sentry_id, peer_id, latency);
+        }
+    }
+
+    /// Write headers on top of the chain ending at `prev_progress`, returns new progress.
+    fn write_headers<E: EnvironmentKind>(
+        &self,
+        txn: &MdbxTransaction<'_, RW, E>,
+        prev_progress: BlockNumber,
+        headers: impl IntoIterator<Item = (H256, BlockHeader)>,
+    ) -> anyhow::Result<BlockNumber> {
+        let mut cursor_header_number = txn.cursor(tables::HeaderNumber)?;
+        let mut cursor_header = txn.cursor(tables::Header)?;
+        let mut cursor_canonical = txn.cursor(tables::CanonicalHeader)?;
+        let mut cursor_td = txn.cursor(tables::HeadersTotalDifficulty)?;
+
+        let mut stage_progress = prev_progress;
+        let mut headers = headers.into_iter().peekable();
+
+        // Headers kept by a soft unwind only need their canonical pointers restored
+        let mut retained = Vec::new();
+        for stored in txn.cursor(tables::Header)?.walk(Some(prev_progress + 1_u8)) {
+            let (number, stored) = stored?;
+            if number > self.max_block {
+                break;
+            }
+            if let Some((hash, _)) =
+                headers.next_if(|(_, header)| header.number == number && *header == stored)
+            {
+                retained.push(hash);
+            } else {
+                break;
+            }
+        }
+        if !retained.is_empty() {
+            let to = prev_progress + retained.len() as u64;
+            accessors::chain::recanonicalize(txn, prev_progress + 1_u8, to, &retained)?;
+            debug!("Re-canonicalized {} stored headers", retained.len());
+            stage_progress = to;
+        }
+
+        // Stored headers of a chain that lost
+        while let Some((number, _)) = cursor_header.last()? {
+            if number <= stage_progress {
+                break;
+            }
+            cursor_header.delete_current()?;
+        }
+        while let Some((number, _)) = cursor_td.last()? {
+            if number <= stage_progress {
+                break;
+            }
+            cursor_td.delete_current()?;
+        }
+
+        let mut td = if self.compute_td {
+            Some(match cursor_td.last()? {
+                Some((number, td)) if number == stage_progress => td,
+                Some((number, _)) => bail!(
+                    "Total difficulty is stored up to #{number} only, below #{stage_progress}, repair it first"
+                ),
+                None if stage_progress == 0 => {
+                    // Fresh database without genesis total difficulty
+                    let genesis_difficulty = accessors::chain::header::read(txn, BlockNumber(0))?
+                        .map(|genesis| genesis.difficulty)
+                        .unwrap_or_default();
+                    info!(
+                        "No total difficulty stored, starting from genesis difficulty {genesis_difficulty}"
+                    );
+                    cursor_td.append(BlockNumber(0), genesis_difficulty)?;
+                    genesis_difficulty
+                }
+                None => bail!("No total difficulty stored for #{stage_progress}, repair it first"),
+            })
+        } else {
+            None
+        };
+
+        let mut td_mismatches = HashSet::new();
+        while let Some((hash, header)) = headers.next() {
+            if header.number == 0 {
+                continue;
+            }
+            if header.number > self.max_block {
+                if let Some(orphans) = &self.orphans {
+                    let excess = std::iter::once((hash, header))
+                        .chain(headers.by_ref())
+                        .collect::<Vec<_>>();
+                    debug!(
+                        "Keeping {} headers above max block #{}",
+                        excess.len(),
+                        self.max_block
+                    );
+                    orphans.insert(excess);
+                }
+                break;
+            }
+
+            let block_number = header.number;
+            if block_number <= stage_progress {
+                bail!("Header #{block_number} ({hash:?}) is not above #{stage_progress}");
+            }
+
+            if let Some(td) = &mut td {
+                *td += header.difficulty;
+                cursor_td.append(block_number, *td)?;
+
+                let claims = self.node.td_claims.lock().remove(&hash);
+                for ((sentry_id, peer_id), claimed) in claims.into_iter().flatten() {
+                    if Self::td_mismatch(claimed, *td) {
+                        warn!("Peer {peer_id} claimed total difficulty {claimed} for header #{block_number} ({hash:?}), computed {td}");
+                        td_mismatches.insert((sentry_id, peer_id));
+                    }
+                }
+            }
+            if self.build_header_number {
+                cursor_header_number.put(hash, block_number)?;
+            }
+            cursor_header.append(block_number, header)?;
+            cursor_canonical.append(block_number, hash)?;
+
+            stage_progress = block_number;
+        }
+
+        if !td_mismatches.is_empty() {
+            // Penalties are applied in background after the queue is dropped
+            let penalties = PenaltyQueue::new(self.node.clone(), self.penalize_workers);
+            for (sentry_id, peer_id) in td_mismatches {
+                self.node.record_peer_failure(sentry_id, peer_id);
+                penalties.penalize(peer_id, PenaltyKind::TdMismatch);
             }
         }
+
+        Ok(stage_progress)
+    }
+
+    /// Whether a peer's total difficulty claim is off by more than the tolerance.
+    fn td_mismatch(claimed: U256, computed: U256) -> bool {
+        let diff = if claimed > computed {
+            claimed - computed
+        } else {
+            computed - claimed
+        };
+        diff > computed / U256::from(TD_TOLERANCE_DIVISOR)
     }
 
     async fn update_head<'tx, E: EnvironmentKind>(
@@ -642,40 +1254,225 @@ impl HeaderDownload {
         height: BlockNumber,
     ) -> anyhow::Result<()> {
         let hash = txn.get(tables::CanonicalHeader, height)?.unwrap();
-        let td = txn.get(tables::HeadersTotalDifficulty, height)?.unwrap();
+        let td = txn
+            .get(tables::HeadersTotalDifficulty, height)?
+            .unwrap_or_default();
         let status = Status::new(height, hash, td);
         self.node.update_chain_head(Some(status)).await;
         Ok(())
     }
 
-    fn forward_set_target_block(
-        prev_progress: BlockNumber,
-        increment: Option<BlockNumber>,
-        chain_tip: BlockNumber,
-    ) -> (BlockNumber, bool) {
-        let max_increment = std::cmp::max(
-            BlockNumber(1),
-            increment
-                .map(|v| std::cmp::min(v, STAGE_UPPER_BOUND))
-                .unwrap_or(STAGE_UPPER_BOUND),
-        );
-        let max_incremented_from_start = BlockNumber(prev_progress.0 + max_increment.0);
+    fn chain_tip_source(&self) -> &dyn ChainTipSource {
+        self.chain_tip.as_deref().unwrap_or(&*self.node)
+    }
 
-        if max_incremented_from_start > chain_tip {
-            (chain_tip, true)
-        } else {
-            (max_incremented_from_start, false)
+    /// Wait for chain tip to move past `prev_progress` and pick the last block to download in this run,
+    /// stopping at the fork choice head when its number is known.
+    /// Returns the target and whether it is the tip.
+    async fn next_target(&self, prev_progress: BlockNumber) -> (BlockNumber, bool) {
+        let (current_chain_tip, _) = self.chain_tip_source().wait_above(prev_progress).await;
+
+        debug!("Chain tip={}", current_chain_tip);
+
+        let (mut target_block, mut reached_tip) =
+            Self::forward_set_target_block(prev_progress, self.increment, current_chain_tip);
+
+        // Do not download past the head chosen by the consensus layer
+        if let Some(head) = self.node.forkchoice_head_number() {
+            if head > prev_progress && head <= target_block {
+                target_block = head;
+                reached_tip = true;
+            }
         }
+
+        if target_block >= self.max_block {
+            target_block = self.max_block;
+            reached_tip = true;
+        }
+
+        (target_block, reached_tip)
     }
 
-    fn prepare_requests(
-        starting_block: BlockNumber,
-        target: BlockNumber,
-    ) -> DashMap<BlockNumber, HeaderRequest> {
-        (starting_block..=target)
-            .step_by(HEADERS_UPPER_BOUND)
-            .map(|start| {
-                let limit = std::cmp::min(*target - *start + 1, HEADERS_UPPER_BOUND as u64);
+    /// Check that the database was initialized for the network whose genesis is `expected_genesis_hash`.
+    pub fn verify_genesis<K: TransactionKind, E: EnvironmentKind>(
+        txn: &MdbxTransaction<'_, K, E>,
+        expected_genesis_hash: H256,
+    ) -> anyhow::Result<()> {
+        let genesis_hash = txn
+            .get(tables::CanonicalHeader, BlockNumber(0))?
+            .ok_or_else(|| format_err!("no genesis block in database"))?;
+
+        if genesis_hash != expected_genesis_hash {
+            bail!(
+                "wrong network: database genesis {genesis_hash:?} does not match expected genesis {expected_genesis_hash:?}"
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Canonical headers above `prev_progress_header` up to `target`, for as long as they form a chain linked to it.
+    /// Headers kept by a soft unwind have no canonical entry and are not returned.
+    fn stored_headers_above<K: TransactionKind, E: EnvironmentKind>(
+        txn: &MdbxTransaction<'_, K, E>,
+        prev_progress_header: &BlockHeader,
+        target: BlockNumber,
+    ) -> anyhow::Result<Vec<(H256, BlockHeader)>> {
+        let mut headers = Vec::new();
+
+        let mut expected_number = prev_progress_header.number + 1_u8;
+        let mut parent_hash = prev_progress_header.hash();
+        let mut header_cursor = txn.cursor(tables::Header)?;
+        let mut walker = txn
+            .cursor(tables::CanonicalHeader)?
+            .walk(Some(expected_number));
+        while let Some((number, canonical_hash)) = walker.next().transpose()? {
+            if number > target || number != expected_number {
+                break;
+            }
+            let header = match header_cursor.seek_exact(number)? {
+                Some((_, header)) => header,
+                None => break,
+            };
+            let hash = header.hash();
+            if hash != canonical_hash || header.parent_hash != parent_hash {
+                break;
+            }
+
+            expected_number = number + 1_u8;
+            parent_hash = hash;
+            headers.push((hash, header));
+        }
+
+        Ok(headers)
+    }
+
+    /// Headers above `anchor` and below `target` left in `Header` by a soft unwind, for as long as they form
+    /// a chain linked to it. Only one header is stored per block number, so a chain written over them since
+    /// is not recovered.
+    fn kept_headers_below<K: TransactionKind, E: EnvironmentKind>(
+        txn: &MdbxTransaction<'_, K, E>,
+        anchor: &BlockHeader,
+        target: BlockNumber,
+    ) -> anyhow::Result<Vec<(H256, BlockHeader)>> {
+        let mut headers = Vec::new();
+
+        let mut expected_number = anchor.number + 1_u8;
+        let mut parent_hash = anchor.hash();
+        let mut walker = txn.cursor(tables::Header)?.walk(Some(expected_number));
+        while let Some((number, header)) = walker.next().transpose()? {
+            if number >= target || number != expected_number || header.parent_hash != parent_hash {
+                break;
+            }
+
+            let hash = header.hash();
+            expected_number = number + 1_u8;
+            parent_hash = hash;
+            headers.push((hash, header));
+        }
+
+        Ok(headers)
+    }
+
+    /// First block and parent header for the next download session on top of `buffered` headers.
+    fn session_anchor(
+        prev_progress_header: &BlockHeader,
+        buffered: &[(H256, BlockHeader)],
+    ) -> (BlockNumber, BlockHeader) {
+        let anchor = buffered
+            .last()
+            .map(|(_, header)| header)
+            .unwrap_or(prev_progress_header)
+            .clone();
+
+        (anchor.number + 1_u8, anchor)
+    }
+
+    fn forward_set_target_block(
+        prev_progress: BlockNumber,
+        increment: Option<BlockNumber>,
+        chain_tip: BlockNumber,
+    ) -> (BlockNumber, bool) {
+        let max_increment = std::cmp::max(
+            BlockNumber(1),
+            increment
+                .map(|v| std::cmp::min(v, STAGE_UPPER_BOUND))
+                .unwrap_or(STAGE_UPPER_BOUND),
+        );
+        let max_incremented_from_start = BlockNumber(prev_progress.0 + max_increment.0);
+
+        if max_incremented_from_start > chain_tip {
+            (chain_tip, true)
+        } else {
+            (max_incremented_from_start, false)
+        }
+    }
+
+    /// Drop or shorten requests for blocks above `tip`, which peers cannot serve after the chain tip moved down.
+    /// Returns the number of dropped requests.
+    fn drop_requests_above(
+        requests: &DashMap<BlockNumber, HeaderRequest>,
+        tip: BlockNumber,
+    ) -> usize {
+        let before = requests.len();
+        requests.retain(|&start, request| {
+            if start > tip {
+                return false;
+            }
+            request.limit = request.limit.min(*tip - *start + 1);
+            true
+        });
+        before - requests.len()
+    }
+
+    /// Outstanding requests which were never sent or were last sent at least `retry_interval` ago.
+    /// Returned requests are recorded as sent at `now`.
+    /// Remember when requests were routed to each of their peers, keyed by request start and peer.
+    fn record_sent(
+        sent_at: &DashMap<(BlockNumber, SentryId, H512), Instant>,
+        time: Instant,
+        routed: Vec<(HeaderRequest, HashSet<(SentryId, H512)>)>,
+    ) {
+        for (request, peers) in routed {
+            if let BlockId::Number(start) = request.start {
+                for (sentry_id, peer_id) in peers {
+                    sent_at.insert((start, sentry_id, peer_id), time);
+                }
+            }
+        }
+    }
+
+    fn due_requests(
+        requests: &DashMap<BlockNumber, HeaderRequest>,
+        last_sent: &mut HashMap<BlockNumber, Instant>,
+        retry_interval: Duration,
+        now: Instant,
+    ) -> Vec<HeaderRequest> {
+        last_sent.retain(|number, _| requests.contains_key(number));
+
+        requests
+            .iter()
+            .filter_map(|entry| {
+                let number = *entry.key();
+                if let Some(sent) = last_sent.get(&number) {
+                    if now.duration_since(*sent) < retry_interval {
+                        return None;
+                    }
+                }
+                last_sent.insert(number, now);
+                Some(*entry.value())
+            })
+            .collect()
+    }
+
+    fn prepare_requests(
+        starting_block: BlockNumber,
+        target: BlockNumber,
+    ) -> DashMap<BlockNumber, HeaderRequest> {
+        (starting_block..=target)
+            .step_by(HEADERS_UPPER_BOUND)
+            .map(|start| {
+                let limit = std::cmp::min(*target - *start + 1, HEADERS_UPPER_BOUND as u64);
 
                 let request = HeaderRequest {
                     start: BlockId::Number(start),
@@ -688,6 +1485,19 @@ impl HeaderDownload {
             .collect()
     }
 
+    /// Reason to unwind if `header` is not a child of stored block `number` with `hash`.
+    fn parent_mismatch(
+        number: BlockNumber,
+        hash: H256,
+        header: &BlockHeader,
+    ) -> Option<UnwindReason> {
+        (header.parent_hash != hash).then(|| UnwindReason::ParentMismatch {
+            block: number + 1_u8,
+            expected: hash,
+            found: header.parent_hash,
+        })
+    }
+
     #[inline]
     fn check_contiguous(headers: Vec<BlockHeader>) -> Result<Vec<(H256, BlockHeader)>, ()> {
         let headers = headers
@@ -703,6 +1513,28 @@ impl HeaderDownload {
         Ok(headers)
     }
 
+    /// Check headers against `checkpoints`.
+    /// On mismatch returns the number of headers preceding it, and the mismatched hash.
+    fn verify_checkpoints(&self, headers: &[(H256, BlockHeader)]) -> Result<(), (usize, H256)> {
+        if self.checkpoints.is_empty() {
+            return Ok(());
+        }
+
+        for (i, (hash, header)) in headers.iter().enumerate() {
+            if let Some(expected_hash) = self.checkpoints.get(&header.number) {
+                if hash != expected_hash {
+                    warn!(
+                        "Rejected block header #{} ({hash:?}) not matching checkpoint {expected_hash:?}",
+                        header.number
+                    );
+                    return Err((i, *hash));
+                }
+            }
+        }
+
+        Ok(())
+    }
+
     fn validate_sequentially<'a>(
         &self,
         mut parent_header: &'a BlockHeader,
@@ -717,7 +1549,11 @@ impl HeaderDownload {
 
             if let Err(e) = self
                 .consensus
-                .validate_block_header(header, parent_header, false)
+                .validate_difficulty(header, parent_header)
+                .and_then(|_| {
+                    self.consensus
+                        .validate_block_header(header, parent_header, false)
+                })
             {
                 warn!("Rejected bad block header ({hash:?}) for reason {e:?}: {header:?}");
                 return Err((i.saturating_sub(1), *hash));
@@ -728,36 +1564,1393 @@ impl HeaderDownload {
         Ok(())
     }
 
-    fn validate_parallel(&self, headers: &[(H256, BlockHeader)]) -> Result<(), (usize, H256)> {
-        let valid_till = AtomicUsize::new(0);
+    /// Validate ommers of blocks `from..=to` against their canonical parents.
+    /// Returns `(block number, ommer index)` for each ommer that fails validation.
+    pub fn verify_uncles<K: TransactionKind, E: EnvironmentKind>(
+        &self,
+        txn: &MdbxTransaction<'_, K, E>,
+        from: BlockNumber,
+        to: BlockNumber,
+    ) -> anyhow::Result<Vec<(BlockNumber, usize)>> {
+        let mut invalid = vec![];
+        for block_number in from..=to {
+            let body = accessors::chain::storage_body::read(txn, block_number)?
+                .ok_or_else(|| format_err!("Body for block #{block_number} not found"))?;
+
+            for (i, ommer) in body.ommers.iter().enumerate() {
+                let parent = if let Some(parent_number) = ommer.number.0.checked_sub(1) {
+                    accessors::chain::header::read(txn, parent_number)?
+                        .filter(|parent| parent.hash() == ommer.parent_hash)
+                } else {
+                    None
+                };
 
-        headers.par_iter().enumerate().for_each(|(i, (_, header))| {
-            if self.consensus.validate_header_parallel(header).is_err() {
-                let mut value = valid_till.load(Ordering::SeqCst);
-                while i < value {
-                    if valid_till.compare_exchange(value, i, Ordering::SeqCst, Ordering::SeqCst)
-                        == Ok(value)
-                    {
-                        break;
-                    } else {
-                        value = valid_till.load(Ordering::SeqCst);
+                let res = if let Some(parent) = parent {
+                    self.consensus.validate_block_header(ommer, &parent, false)
+                } else {
+                    Err(ValidationError::OmmerUnknownParent {
+                        number: ommer.number,
+                        parent_hash: ommer.parent_hash,
                     }
+                    .into())
+                };
+
+                if let Err(e) = res {
+                    warn!(
+                        "Rejected ommer #{i} of block #{block_number} for reason {e:?}: {ommer:?}"
+                    );
+                    invalid.push((block_number, i));
                 }
             }
-        });
+        }
+
+        Ok(invalid)
+    }
+
+    /// Validate seals of `headers` in parallel. Reasons for all failures are pushed to `failures` if set.
+    fn validate_parallel(
+        consensus: &dyn Consensus,
+        headers: &[(H256, BlockHeader)],
+        cancel: &CancellationToken,
+        failures: Option<&Mutex<Vec<SealFailure>>>,
+    ) -> Result<(), SealValidationError> {
+        let valid_till = AtomicUsize::new(headers.len());
+
+        headers
+            .par_iter()
+            .enumerate()
+            .for_each(|(i, (hash, header))| {
+                if cancel.is_cancelled() {
+                    return;
+                }
+
+                if let Err(e) = consensus.validate_header_parallel(header) {
+                    if let Some(failures) = failures {
+                        failures.lock().push((header.number, *hash, e));
+                    }
+
+                    valid_till.fetch_min(i, Ordering::SeqCst);
+                }
+            });
+
+        if cancel.is_cancelled() {
+            return Err(SealValidationError::Cancelled);
+        }
 
         let valid_till = valid_till.load(Ordering::SeqCst);
-        if valid_till != 0 {
-            Err((valid_till - 1, headers[valid_till].0))
+        if valid_till < headers.len() {
+            Err(SealValidationError::Invalid {
+                first_invalid: valid_till,
+                invalid_hash: headers[valid_till].0,
+            })
         } else {
             Ok(())
         }
     }
 }
 
+/// Check that a batch of headers obtained outside of download, e.g. imported from a file, forms a chain:
+/// each hash is the hash of its header, and each header is the child of the previous one.
+/// Fails at the first header breaking it.
+pub fn validate_batch_linkage(headers: &[(H256, BlockHeader)]) -> anyhow::Result<()> {
+    for (i, (hash, header)) in headers.iter().enumerate() {
+        if header.hash() != *hash {
+            bail!(
+                "header #{} at position {i} does not hash to {hash:?}",
+                header.number
+            );
+        }
+
+        if let Some((parent_hash, parent)) = i.checked_sub(1).map(|p| &headers[p]) {
+            if header.parent_hash != *parent_hash || header.number != parent.number + 1_u8 {
+                bail!(
+                    "header #{} at position {i} is not a child of #{} ({parent_hash:?})",
+                    header.number,
+                    parent.number
+                );
+            }
+        }
+    }
+
+    Ok(())
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::{
+        consensus::{ExternalForkChoice, FinalizationChange},
+        kv::new_mem_chaindata,
+        models::{Address, Block, BodyForStorage, ChainConfig},
+        p2p::node::NodeBuilder,
+        BlockReader,
+    };
+    use std::collections::VecDeque;
+    use tokio::sync::watch;
+
+    /// Extra data of headers `TestConsensus` rejects in seal validation.
+    const BAD_SEAL: &[u8] = b"bad seal";
+
+    #[derive(Debug, Default)]
+    struct TestConsensus {
+        graph: Arc<Mutex<ForkChoiceGraph>>,
+        /// Engine API fork choice, switches to external fork choice mode when set.
+        external: Option<watch::Receiver<ExternalForkChoice>>,
+    }
+
+    impl Consensus for TestConsensus {
+        fn fork_choice_mode(&self) -> ForkChoiceMode {
+            match &self.external {
+                Some(external) => ForkChoiceMode::External(external.clone()),
+                None => ForkChoiceMode::Difficulty(self.graph.clone()),
+            }
+        }
+
+        fn pre_validate_block(&self, _: &Block, _: &dyn BlockReader) -> Result<(), DuoError> {
+            Ok(())
+        }
+
+        fn validate_block_header(
+            &self,
+            header: &BlockHeader,
+            _: &BlockHeader,
+            _: bool,
+        ) -> Result<(), DuoError> {
+            if header.gas_used > header.gas_limit {
+                return Err(ValidationError::GasAboveLimit {
+                    used: header.gas_used,
+                    limit: header.gas_limit,
+                }
+                .into());
+            }
+            Ok(())
+        }
+
+        fn finalize(
+            &self,
+            _: &BlockHeader,
+            _: &[BlockHeader],
+        ) -> anyhow::Result<Vec<FinalizationChange>> {
+            Ok(vec![])
+        }
+
+        fn validate_header_parallel(&self, header: &BlockHeader) -> Result<(), DuoError> {
+            if header.extra_data.as_ref() == BAD_SEAL {
+                return Err(ValidationError::InvalidSeal.into());
+            }
+            Ok(())
+        }
+
+        fn validate_difficulty(
+            &self,
+            header: &BlockHeader,
+            parent: &BlockHeader,
+        ) -> Result<(), DuoError> {
+            // Constant difficulty
+            if header.difficulty != parent.difficulty {
+                return Err(ValidationError::WrongDifficulty.into());
+            }
+            Ok(())
+        }
+    }
+
+    fn header_download() -> HeaderDownload {
+        HeaderDownload {
+            node: Arc::new(
+                NodeBuilder::new(ChainConfig::new("mainnet").unwrap())
+                    .add_sentry(http::Uri::from_static("http://127.0.0.1:8000"))
+                    .build()
+                    .unwrap(),
+            ),
+            consensus: Arc::new(TestConsensus::default()),
+            max_block: BlockNumber(u64::MAX),
+            increment: None,
+            request_limit: None,
+            build_header_number: true,
+            orphans: None,
+            penalize_workers: 1,
+            per_call_deadline: None,
+            retry_interval: None,
+            chain_tip: None,
+            checkpoints: Default::default(),
+            collect_seal_failures: false,
+            compute_td: true,
+            soft_unwind_depth: None,
+        }
+    }
+
+    /// Chain of `len` headers on top of `parent`.
+    fn make_chain(parent: &BlockHeader, len: usize) -> Vec<(H256, BlockHeader)> {
+        let mut parent_hash = parent.hash();
+        (1..=len as u64)
+            .map(|i| {
+                let header = BlockHeader {
+                    parent_hash,
+                    number: parent.number + i,
+                    difficulty: 1_u64.into(),
+                    ..Default::default()
+                };
+                parent_hash = header.hash();
+                (parent_hash, header)
+            })
+            .collect()
+    }
+
+    /// Database with genesis header only.
+    fn seed_genesis<E: EnvironmentKind>(txn: &MdbxTransaction<'_, RW, E>) -> BlockHeader {
+        let genesis = BlockHeader {
+            difficulty: 1_u64.into(),
+            ..Default::default()
+        };
+        txn.set(tables::Header, BlockNumber(0), genesis.clone())
+            .unwrap();
+        txn.set(tables::CanonicalHeader, BlockNumber(0), genesis.hash())
+            .unwrap();
+        txn.set(tables::HeaderNumber, genesis.hash(), BlockNumber(0))
+            .unwrap();
+        txn.set(
+            tables::HeadersTotalDifficulty,
+            BlockNumber(0),
+            genesis.difficulty,
+        )
+        .unwrap();
+        genesis
+    }
+
+    #[test]
+    fn verify_genesis() {
+        let db = new_mem_chaindata().unwrap();
+        let txn = db.begin_mutable().unwrap();
+
+        assert!(HeaderDownload::verify_genesis(&txn, H256::zero()).is_err());
+
+        let genesis = seed_genesis(&txn);
+        HeaderDownload::verify_genesis(&txn, genesis.hash()).unwrap();
+
+        let err = HeaderDownload::verify_genesis(&txn, H256::repeat_byte(0xaa)).unwrap_err();
+        assert!(err.to_string().contains("wrong network"));
+    }
+
+    #[tokio::test]
+    async fn write_headers_empty_td() {
+        let db = new_mem_chaindata().unwrap();
+        let txn = db.begin_mutable().unwrap();
+        let genesis = seed_genesis(&txn);
+        txn.clear_table(tables::HeadersTotalDifficulty).unwrap();
+        let downloader = header_download();
+
+        let chain = make_chain(&genesis, 2);
+        assert_eq!(
+            downloader
+                .write_headers(&txn, BlockNumber(0), chain)
+                .unwrap(),
+            BlockNumber(2)
+        );
+        for (number, td) in [(0, 1_u64), (1, 2), (2, 3)] {
+            assert_eq!(
+                txn.get(tables::HeadersTotalDifficulty, BlockNumber(number))
+                    .unwrap(),
+                Some(td.into())
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn write_headers_missing_td() {
+        let db = new_mem_chaindata().unwrap();
+        let txn = db.begin_mutable().unwrap();
+        let genesis = seed_genesis(&txn);
+        let mut downloader = header_download();
+
+        // Headers imported without total difficulty
+        downloader.compute_td = false;
+        let chain = make_chain(&genesis, 3);
+        downloader
+            .write_headers(&txn, BlockNumber(0), chain[..2].to_vec())
+            .unwrap();
+        downloader.compute_td = true;
+
+        assert!(downloader
+            .write_headers(&txn, BlockNumber(2), chain[2..].to_vec())
+            .is_err());
+
+        txn.clear_table(tables::HeadersTotalDifficulty).unwrap();
+        assert!(downloader
+            .write_headers(&txn, BlockNumber(2), chain[2..].to_vec())
+            .is_err());
+        assert_eq!(
+            txn.cursor(tables::HeadersTotalDifficulty)
+                .unwrap()
+                .last()
+                .unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn write_headers_lazy_td() {
+        let db = new_mem_chaindata().unwrap();
+        let txn = db.begin_mutable().unwrap();
+        let genesis = seed_genesis(&txn);
+        let mut downloader = header_download();
+        downloader.compute_td = false;
+
+        let chain = make_chain(&genesis, 3);
+        assert_eq!(
+            downloader
+                .write_headers(&txn, BlockNumber(0), chain.clone())
+                .unwrap(),
+            BlockNumber(3)
+        );
+        assert_eq!(
+            txn.get(tables::CanonicalHeader, BlockNumber(3)).unwrap(),
+            Some(chain[2].0)
+        );
+        assert_eq!(
+            txn.cursor(tables::HeadersTotalDifficulty)
+                .unwrap()
+                .last()
+                .unwrap(),
+            Some((BlockNumber(0), 1_u64.into()))
+        );
+
+        accessors::chain::td::repair_range(&txn, 1, 3).unwrap();
+        for (number, td) in [(1, 2_u64), (2, 3), (3, 4)] {
+            assert_eq!(
+                txn.get(tables::HeadersTotalDifficulty, BlockNumber(number))
+                    .unwrap(),
+                Some(td.into())
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn write_headers_td_claims() {
+        let db = new_mem_chaindata().unwrap();
+        let txn = db.begin_mutable().unwrap();
+        let genesis = seed_genesis(&txn);
+        let downloader = header_download();
+
+        let chain = make_chain(&genesis, 3);
+        let (honest, liar) = (H512::random(), H512::random());
+        // Liar is penalized once for both claims, including one an honest peer announced after it
+        for (i, peer, td) in [(0, liar, 50), (1, liar, 100), (1, honest, 3_u64)] {
+            downloader
+                .node
+                .record_td_claim(chain[i].0, 0, peer, td.into());
+        }
+
+        downloader
+            .write_headers(&txn, BlockNumber(0), chain)
+            .unwrap();
+
+        assert!(downloader.node.td_claims.lock().is_empty());
+        let failures = |peer| {
+            downloader
+                .node
+                .peer_scores
+                .get(&(0, peer))
+                .map(|score| score.failures)
+                .unwrap_or_default()
+        };
+        assert_eq!(failures(honest), 0);
+        assert_eq!(failures(liar), 1);
+
+        assert!(!HeaderDownload::td_mismatch(
+            1000_u64.into(),
+            1001_u64.into()
+        ));
+        assert!(HeaderDownload::td_mismatch(
+            1000_u64.into(),
+            1002_u64.into()
+        ));
+    }
+
+    #[tokio::test]
+    async fn write_headers_above_max_block() {
+        let db = new_mem_chaindata().unwrap();
+        let txn = db.begin_mutable().unwrap();
+        let genesis = seed_genesis(&txn);
+        let orphans = Arc::new(OrphanPool::new(4));
+        let mut downloader = header_download();
+        downloader.max_block = BlockNumber(3);
+        downloader.orphans = Some(orphans.clone());
+
+        let chain = make_chain(&genesis, 5);
+        assert_eq!(
+            downloader
+                .write_headers(&txn, BlockNumber(0), chain.clone())
+                .unwrap(),
+            BlockNumber(3)
+        );
+        assert_eq!(
+            txn.get(tables::CanonicalHeader, BlockNumber(4)).unwrap(),
+            None
+        );
+
+        // Excess headers attach once download continues from the new progress
+        assert_eq!(orphans.len(), 1);
+        let mut graph = ForkChoiceGraph::new();
+        assert_eq!(
+            orphans.promote(&mut graph, chain[2].0),
+            [4, 5].into_iter().map(BlockNumber).collect::<HashSet<_>>()
+        );
+        assert_eq!(graph.chain_head(), Some(chain[4].0));
+    }
+
+    #[tokio::test]
+    async fn write_headers() {
+        let db = new_mem_chaindata().unwrap();
+        let txn = db.begin_mutable().unwrap();
+        let genesis = seed_genesis(&txn);
+        let downloader = header_download();
+
+        let chain = make_chain(&genesis, 5);
+
+        assert_eq!(
+            downloader
+                .write_headers(&txn, BlockNumber(0), chain[..3].to_vec())
+                .unwrap(),
+            BlockNumber(3)
+        );
+        assert_eq!(
+            txn.get(tables::HeadersTotalDifficulty, BlockNumber(3))
+                .unwrap(),
+            Some(4_u64.into())
+        );
+
+        // Out-of-order slice
+        assert!(downloader
+            .write_headers(
+                &txn,
+                BlockNumber(3),
+                vec![chain[4].clone(), chain[3].clone()]
+            )
+            .is_err());
+        // Overlapping slice
+        assert!(downloader
+            .write_headers(&txn, BlockNumber(3), chain[2..].to_vec())
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn verify_uncles() {
+        let db = new_mem_chaindata().unwrap();
+        let txn = db.begin_mutable().unwrap();
+        let genesis = seed_genesis(&txn);
+        let downloader = header_download();
+
+        let chain = make_chain(&genesis, 3);
+        downloader
+            .write_headers(&txn, BlockNumber(0), chain.clone())
+            .unwrap();
+
+        let valid_ommer = BlockHeader {
+            parent_hash: genesis.hash(),
+            number: BlockNumber(1),
+            beneficiary: Address::repeat_byte(0xaa),
+            ..Default::default()
+        };
+        let invalid_ommer = BlockHeader {
+            gas_used: 1,
+            ..valid_ommer.clone()
+        };
+        let orphan_ommer = BlockHeader {
+            parent_hash: H256::repeat_byte(0xbb),
+            number: BlockNumber(2),
+            ..Default::default()
+        };
+
+        for (block_number, ommers) in [
+            (BlockNumber(1), vec![]),
+            (BlockNumber(2), vec![valid_ommer, invalid_ommer]),
+            (BlockNumber(3), vec![orphan_ommer]),
+        ] {
+            accessors::chain::storage_body::write(
+                &txn,
+                block_number,
+                &BodyForStorage {
+                    base_tx_id: 0.into(),
+                    tx_amount: 0,
+                    ommers: ommers.into_iter().collect(),
+                    withdrawals: None,
+                },
+            )
+            .unwrap();
+        }
+
+        assert_eq!(
+            downloader
+                .verify_uncles(&txn, BlockNumber(1), BlockNumber(3))
+                .unwrap(),
+            vec![(BlockNumber(2), 1), (BlockNumber(3), 0)]
+        );
+        assert!(downloader
+            .verify_uncles(&txn, BlockNumber(1), BlockNumber(4))
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn validate_difficulty() {
+        let downloader = header_download();
+        let genesis = BlockHeader {
+            difficulty: 1_u64.into(),
+            ..Default::default()
+        };
+
+        let mut headers = make_chain(&genesis, 3);
+        assert_eq!(downloader.validate_sequentially(&genesis, &headers), Ok(()));
+
+        let (hash, header) = headers.last_mut().unwrap();
+        header.difficulty = 2_u64.into();
+        *hash = header.hash();
+        let bad_hash = *hash;
+
+        assert_eq!(
+            downloader.validate_sequentially(&genesis, &headers),
+            Err((1, bad_hash))
+        );
+    }
+
+    #[tokio::test]
+    async fn soft_unwind() {
+        let db = new_mem_chaindata().unwrap();
+        let mut txn = db.begin_mutable().unwrap();
+        let genesis = seed_genesis(&txn);
+        let mut downloader = header_download();
+        downloader.soft_unwind_depth = Some(2);
+
+        let chain = make_chain(&genesis, 4);
+        downloader
+            .write_headers(&txn, BlockNumber(0), chain.clone())
+            .unwrap();
+
+        let unwind = |stage_progress, unwind_to| UnwindInput {
+            stage_progress: BlockNumber(stage_progress),
+            unwind_to: BlockNumber(unwind_to),
+            bad_block: None,
+        };
+        downloader.unwind(&mut txn, unwind(4, 2)).await.unwrap();
+        assert_eq!(
+            txn.get(tables::CanonicalHeader, BlockNumber(3)).unwrap(),
+            None
+        );
+        assert_eq!(txn.get(tables::HeaderNumber, chain[2].0).unwrap(), None);
+        assert_eq!(
+            txn.get(tables::Header, BlockNumber(4)).unwrap(),
+            Some(chain[3].1.clone())
+        );
+        // Kept headers are not canonical, they are reused below the target only
+        assert_eq!(
+            HeaderDownload::stored_headers_above(&txn, &chain[1].1, BlockNumber(10)).unwrap(),
+            vec![]
+        );
+        assert_eq!(
+            HeaderDownload::kept_headers_below(&txn, &chain[1].1, BlockNumber(4)).unwrap(),
+            chain[2..3].to_vec()
+        );
+        assert_eq!(
+            HeaderDownload::kept_headers_below(&txn, &chain[1].1, BlockNumber(10)).unwrap(),
+            chain[2..].to_vec()
+        );
+        assert_eq!(
+            HeaderDownload::kept_headers_below(
+                &txn,
+                &BlockHeader {
+                    number: BlockNumber(2),
+                    ..Default::default()
+                },
+                BlockNumber(10)
+            )
+            .unwrap(),
+            vec![]
+        );
+
+        // Same chain wins again
+        assert_eq!(
+            downloader
+                .write_headers(&txn, BlockNumber(2), chain[2..].to_vec())
+                .unwrap(),
+            BlockNumber(4)
+        );
+        assert_eq!(
+            txn.get(tables::CanonicalHeader, BlockNumber(4)).unwrap(),
+            Some(chain[3].0)
+        );
+        assert_eq!(
+            txn.get(tables::HeaderNumber, chain[3].0).unwrap(),
+            Some(BlockNumber(4))
+        );
+        assert_eq!(
+            txn.get(tables::HeadersTotalDifficulty, BlockNumber(4))
+                .unwrap(),
+            Some(5_u64.into())
+        );
+
+        // Different chain replaces kept headers
+        downloader.unwind(&mut txn, unwind(4, 2)).await.unwrap();
+        let fork = make_chain(&chain[1].1, 1)
+            .into_iter()
+            .map(|(_, header)| {
+                let header = BlockHeader {
+                    gas_limit: 1,
+                    ..header
+                };
+                (header.hash(), header)
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            downloader
+                .write_headers(&txn, BlockNumber(2), fork.clone())
+                .unwrap(),
+            BlockNumber(3)
+        );
+        assert_eq!(
+            txn.get(tables::Header, BlockNumber(3)).unwrap(),
+            Some(fork[0].1.clone())
+        );
+        assert_eq!(txn.get(tables::Header, BlockNumber(4)).unwrap(), None);
+        assert_eq!(
+            txn.get(tables::HeadersTotalDifficulty, BlockNumber(4))
+                .unwrap(),
+            None
+        );
+
+        // Repeated soft unwinds keep at most `soft_unwind_depth` headers
+        downloader.unwind(&mut txn, unwind(3, 2)).await.unwrap();
+        downloader.unwind(&mut txn, unwind(2, 1)).await.unwrap();
+        assert_eq!(
+            txn.get(tables::Header, BlockNumber(3)).unwrap(),
+            Some(fork[0].1.clone())
+        );
+        downloader.unwind(&mut txn, unwind(1, 0)).await.unwrap();
+        assert_eq!(
+            txn.get(tables::Header, BlockNumber(2)).unwrap(),
+            Some(chain[1].1.clone())
+        );
+        assert_eq!(txn.get(tables::Header, BlockNumber(3)).unwrap(), None);
+        assert_eq!(
+            txn.get(tables::HeadersTotalDifficulty, BlockNumber(3))
+                .unwrap(),
+            None
+        );
+
+        // Deeper unwinds delete headers
+        downloader.unwind(&mut txn, unwind(3, 0)).await.unwrap();
+        assert_eq!(txn.get(tables::Header, BlockNumber(1)).unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn unwind_past_finalized() {
+        let db = new_mem_chaindata().unwrap();
+        let mut txn = db.begin_mutable().unwrap();
+        let genesis = seed_genesis(&txn);
+        let mut downloader = header_download();
+
+        let chain = make_chain(&genesis, 4);
+        downloader
+            .write_headers(&txn, BlockNumber(0), chain.clone())
+            .unwrap();
+
+        downloader
+            .node
+            .set_forkchoice(chain[3].0, chain[2].0, chain[1].0);
+
+        assert!(downloader
+            .unwind(
+                &mut txn,
+                UnwindInput {
+                    stage_progress: BlockNumber(4),
+                    unwind_to: BlockNumber(1),
+                    bad_block: None,
+                },
+            )
+            .await
+            .is_err());
+        assert_eq!(
+            txn.get(tables::CanonicalHeader, BlockNumber(4)).unwrap(),
+            Some(chain[3].0)
+        );
+
+        downloader
+            .unwind(
+                &mut txn,
+                UnwindInput {
+                    stage_progress: BlockNumber(4),
+                    unwind_to: BlockNumber(2),
+                    bad_block: None,
+                },
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            txn.get(tables::CanonicalHeader, BlockNumber(3)).unwrap(),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn unwind_past_engine_api_finalized() {
+        let db = new_mem_chaindata().unwrap();
+        let mut txn = db.begin_mutable().unwrap();
+        let genesis = seed_genesis(&txn);
+        let mut downloader = header_download();
+
+        let chain = make_chain(&genesis, 4);
+        downloader
+            .write_headers(&txn, BlockNumber(0), chain.clone())
+            .unwrap();
+
+        // Fork choice reaches the consensus engine but not the node
+        let (_sender, external) = watch::channel(ExternalForkChoice {
+            head_block: chain[3].0,
+            safe_block: chain[2].0,
+            finalized_block: chain[1].0,
+        });
+        downloader.consensus = Arc::new(TestConsensus {
+            external: Some(external),
+            ..Default::default()
+        });
+
+        assert!(downloader
+            .unwind(
+                &mut txn,
+                UnwindInput {
+                    stage_progress: BlockNumber(4),
+                    unwind_to: BlockNumber(1),
+                    bad_block: None,
+                },
+            )
+            .await
+            .is_err());
+        assert_eq!(
+            txn.get(tables::CanonicalHeader, BlockNumber(4)).unwrap(),
+            Some(chain[3].0)
+        );
+    }
+
+    #[tokio::test]
+    async fn resume_stored_headers() {
+        let db = new_mem_chaindata().unwrap();
+        let txn = db.begin_mutable().unwrap();
+        let genesis = seed_genesis(&txn);
+
+        // Interrupted run left headers 1..=3 and an unrelated header at 5
+        let chain = make_chain(&genesis, 5);
+        for (hash, header) in &chain[..3] {
+            txn.set(tables::Header, header.number, header.clone())
+                .unwrap();
+            txn.set(tables::CanonicalHeader, header.number, *hash)
+                .unwrap();
+        }
+        let unrelated = BlockHeader {
+            number: BlockNumber(5),
+            ..Default::default()
+        };
+        txn.set(tables::CanonicalHeader, BlockNumber(5), unrelated.hash())
+            .unwrap();
+        txn.set(tables::Header, BlockNumber(5), unrelated).unwrap();
+
+        let stored = HeaderDownload::stored_headers_above(&txn, &genesis, BlockNumber(10)).unwrap();
+        assert_eq!(stored, chain[..3]);
+        assert_eq!(
+            HeaderDownload::stored_headers_above(&txn, &genesis, BlockNumber(2)).unwrap(),
+            chain[..2]
+        );
+
+        // Download resumes right after stored headers
+        let (starting_block, anchor) = HeaderDownload::session_anchor(&genesis, &stored);
+        assert_eq!(starting_block, BlockNumber(4));
+        assert_eq!(anchor.hash(), chain[3].1.parent_hash);
+
+        // Headers that do not link to the database progress are not reused
+        let fork = make_chain(
+            &BlockHeader {
+                gas_limit: 1,
+                ..genesis.clone()
+            },
+            1,
+        );
+        txn.set(tables::Header, BlockNumber(1), fork[0].1.clone())
+            .unwrap();
+        assert_eq!(
+            HeaderDownload::stored_headers_above(&txn, &genesis, BlockNumber(10)).unwrap(),
+            vec![]
+        );
+    }
+
+    /// Chain tip that moves to the next scheduled value every time it is awaited.
+    #[derive(Debug)]
+    struct ScheduledChainTip {
+        schedule: Mutex<VecDeque<BlockNumber>>,
+        current: Mutex<BlockNumber>,
+    }
+
+    impl ScheduledChainTip {
+        fn new(schedule: impl IntoIterator<Item = u64>) -> Self {
+            Self {
+                schedule: Mutex::new(schedule.into_iter().map(BlockNumber).collect()),
+                current: Mutex::new(BlockNumber(0)),
+            }
+        }
+    }
+
+    #[async_trait]
+    impl ChainTipSource for ScheduledChainTip {
+        fn current(&self) -> (BlockNumber, H256) {
+            (*self.current.lock(), H256::zero())
+        }
+
+        async fn wait_above(&self, number: BlockNumber) -> (BlockNumber, H256) {
+            loop {
+                let current = *self.current.lock();
+                if current > number {
+                    return (current, H256::zero());
+                }
+
+                let next = self
+                    .schedule
+                    .lock()
+                    .pop_front()
+                    .expect("chain tip schedule exhausted");
+                *self.current.lock() = next;
+            }
+        }
+    }
+
+    /// Chain tip which is at `current` after having been waited for at `waited`.
+    struct MovedChainTip {
+        waited: BlockNumber,
+        current: BlockNumber,
+    }
+
+    #[async_trait]
+    impl ChainTipSource for MovedChainTip {
+        fn current(&self) -> (BlockNumber, H256) {
+            (self.current, H256::zero())
+        }
+
+        async fn wait_above(&self, _: BlockNumber) -> (BlockNumber, H256) {
+            (self.waited, H256::zero())
+        }
+    }
+
+    #[tokio::test]
+    async fn next_target() {
+        let mut downloader = header_download();
+        downloader.chain_tip = Some(Arc::new(ScheduledChainTip::new([5, 50, 200_000])));
+
+        // Waits until the tip moves past progress
+        assert_eq!(
+            downloader.next_target(BlockNumber(10)).await,
+            (BlockNumber(50), true)
+        );
+        assert_eq!(
+            downloader.chain_tip_source().current(),
+            (BlockNumber(50), H256::zero())
+        );
+
+        // Far tip is approached in increments
+        assert_eq!(
+            downloader.next_target(BlockNumber(50)).await,
+            (BlockNumber(50) + STAGE_UPPER_BOUND, false)
+        );
+
+        // Target is capped by max block
+        downloader.max_block = BlockNumber(1_000);
+        assert_eq!(
+            downloader.next_target(BlockNumber(100)).await,
+            (BlockNumber(1_000), true)
+        );
+    }
+
+    #[tokio::test]
+    async fn next_target_forkchoice_head() {
+        let mut downloader = header_download();
+        downloader.chain_tip = Some(Arc::new(ScheduledChainTip::new([50])));
+
+        let head = Block {
+            header: BlockHeader {
+                number: BlockNumber(20),
+                ..Default::default()
+            },
+            transactions: vec![],
+            ommers: Default::default(),
+        };
+        let head_hash = head.header.hash();
+
+        // Head number is not known yet, download towards peers' tip
+        downloader
+            .node
+            .set_forkchoice(head_hash, H256::zero(), H256::zero());
+        assert_eq!(
+            downloader.next_target(BlockNumber(10)).await,
+            (BlockNumber(50), true)
+        );
+
+        // Known head caps the target
+        downloader
+            .node
+            .block_cache
+            .lock()
+            .insert(head_hash, (0, H512::zero(), head));
+        assert_eq!(
+            downloader.next_target(BlockNumber(10)).await,
+            (BlockNumber(20), true)
+        );
+
+        // Head at or below progress is ignored
+        assert_eq!(
+            downloader.next_target(BlockNumber(30)).await,
+            (BlockNumber(50), true)
+        );
+    }
+
+    #[tokio::test]
+    async fn verify_checkpoints() {
+        let mut downloader = header_download();
+        let chain = make_chain(&BlockHeader::default(), 5);
+        assert_eq!(downloader.verify_checkpoints(&chain), Ok(()));
+
+        downloader.checkpoints = [(BlockNumber(2), chain[1].0), (BlockNumber(9), H256::zero())]
+            .into_iter()
+            .collect();
+        assert_eq!(downloader.verify_checkpoints(&chain), Ok(()));
+
+        downloader
+            .checkpoints
+            .insert(BlockNumber(4), H256::repeat_byte(0xaa));
+        assert_eq!(downloader.verify_checkpoints(&chain), Err((3, chain[3].0)));
+    }
+
+    #[tokio::test]
+    async fn next_before_deadline() {
+        let mut slow = Box::pin(tokio_stream::iter([1, 2]).then(|item| async move {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            item
+        }));
+
+        // Unbounded wait yields the item
+        assert_eq!(
+            HeaderDownload::next_before(&mut slow, None).await,
+            Some(Some(1))
+        );
+        // Deadline passes before the next item arrives
+        assert_eq!(
+            HeaderDownload::next_before(
+                &mut slow,
+                Some(Instant::now() + Duration::from_millis(10))
+            )
+            .await,
+            None
+        );
+
+        let mut ready = tokio_stream::iter([3]);
+        assert_eq!(
+            HeaderDownload::next_before(&mut ready, Some(Instant::now() + Duration::from_secs(1)))
+                .await,
+            Some(Some(3))
+        );
+        assert_eq!(
+            HeaderDownload::next_before(&mut ready, Some(Instant::now() + Duration::from_secs(1)))
+                .await,
+            Some(None)
+        );
+    }
+
+    #[tokio::test]
+    async fn download_headers_stream_ended() {
+        // No sentry is reachable, so the header stream ends right away
+        let mut downloader = header_download();
+        downloader.chain_tip = Some(Arc::new(MovedChainTip {
+            waited: BlockNumber(10),
+            current: BlockNumber(10),
+        }));
+        let genesis = BlockHeader::default();
+
+        let res = tokio::time::timeout(
+            Duration::from_secs(10),
+            downloader.download_headers(
+                Arc::new(Mutex::new(ForkChoiceGraph::new())),
+                &genesis,
+                BlockNumber(1),
+                BlockNumber(10),
+            ),
+        )
+        .await
+        .expect("download must not spin on an ended stream");
+
+        assert!(res.unwrap_err().to_string().contains("Header stream ended"));
+    }
+
+    #[tokio::test]
+    async fn execute_lowered_tip() {
+        let db = new_mem_chaindata().unwrap();
+        let mut txn = db.begin_mutable().unwrap();
+        let genesis = seed_genesis(&txn);
+
+        // Interrupted run left headers 1..=3
+        let chain = make_chain(&genesis, 3);
+        for (hash, header) in &chain {
+            txn.set(tables::Header, header.number, header.clone())
+                .unwrap();
+            txn.set(tables::CanonicalHeader, header.number, *hash)
+                .unwrap();
+        }
+
+        // Tip moves down to #3 after the run targeted #10, next session starts above it
+        let mut downloader = header_download();
+        downloader.chain_tip = Some(Arc::new(MovedChainTip {
+            waited: BlockNumber(10),
+            current: BlockNumber(3),
+        }));
+
+        let output = tokio::time::timeout(
+            Duration::from_secs(10),
+            downloader.execute(
+                &mut txn,
+                StageInput {
+                    restarted: false,
+                    first_started_at: (std::time::Instant::now(), None),
+                    previous_stage: None,
+                    stage_progress: Some(BlockNumber(0)),
+                },
+            ),
+        )
+        .await
+        .expect("run must stop at the lowered tip")
+        .unwrap();
+
+        assert_eq!(
+            output,
+            ExecOutput::Progress {
+                stage_progress: BlockNumber(3),
+                done: true,
+                reached_tip: true,
+            }
+        );
+        assert_eq!(
+            txn.get(tables::HeaderNumber, chain[2].0).unwrap(),
+            Some(BlockNumber(3))
+        );
+    }
+
+    #[test]
+    fn parent_mismatch() {
+        let genesis = BlockHeader::default();
+        let chain = make_chain(&genesis, 2);
+
+        assert_eq!(
+            HeaderDownload::parent_mismatch(BlockNumber(0), genesis.hash(), &chain[0].1),
+            None
+        );
+        assert_eq!(
+            HeaderDownload::parent_mismatch(BlockNumber(0), genesis.hash(), &chain[1].1),
+            Some(UnwindReason::ParentMismatch {
+                block: BlockNumber(1),
+                expected: genesis.hash(),
+                found: chain[0].0,
+            })
+        );
+    }
+
+    #[test]
+    fn validate_batch_linkage() {
+        let genesis = BlockHeader::default();
+        let chain = make_chain(&genesis, 4);
+
+        super::validate_batch_linkage(&chain).unwrap();
+        super::validate_batch_linkage(&chain[1..]).unwrap();
+        super::validate_batch_linkage(&[]).unwrap();
+
+        // Gap
+        let gapped = [chain[0].clone(), chain[2].clone(), chain[3].clone()];
+        assert!(super::validate_batch_linkage(&gapped)
+            .unwrap_err()
+            .to_string()
+            .contains("position 1"));
+
+        // Hash not matching its header
+        let mut bad_hash = chain.clone();
+        bad_hash[3].0 = H256::repeat_byte(0xff);
+        assert!(super::validate_batch_linkage(&bad_hash)
+            .unwrap_err()
+            .to_string()
+            .contains("position 3"));
+
+        // Linked by hash, but with a wrong number
+        let mut bad_number = chain.clone();
+        bad_number[2].1.number = BlockNumber(10);
+        bad_number[2].0 = bad_number[2].1.hash();
+        bad_number[3].1.parent_hash = bad_number[2].0;
+        bad_number[3].0 = bad_number[3].1.hash();
+        assert!(super::validate_batch_linkage(&bad_number)
+            .unwrap_err()
+            .to_string()
+            .contains("position 2"));
+    }
+
+    #[tokio::test]
+    async fn penalty_queue() {
+        let downloader = header_download();
+        let node = downloader.node.clone();
+        // Each penalty completes only once both workers are applying one
+        let barrier = Arc::new(tokio::sync::Barrier::new(2));
+        let penalties = PenaltyQueue::with_penalizer(node.clone(), 2, move |_| {
+            let barrier = barrier.clone();
+            async move {
+                barrier.wait().await;
+            }
+        });
+
+        let peer = H512::random();
+        penalties.penalize(peer, PenaltyKind::BadBlock);
+        penalties.penalize(H512::random(), PenaltyKind::InvalidSeal);
+        // Repeated penalty for the same peer is not queued
+        penalties.penalize(peer, PenaltyKind::TdMismatch);
+        assert_eq!(penalties.penalized.len(), 2);
+
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while penalties.pending() > 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("penalties are applied concurrently");
+        assert_eq!(node.pending_penalties.load(Ordering::SeqCst), 0);
+    }
+
+    #[test]
+    fn session_anchor() {
+        let genesis = BlockHeader::default();
+        let chain = make_chain(&genesis, 6);
+
+        assert_eq!(
+            HeaderDownload::session_anchor(&genesis, &[]),
+            (BlockNumber(1), genesis.clone())
+        );
+
+        // Next session after buffering a batch attaches to buffered tip, not to database progress
+        let (starting_block, anchor) = HeaderDownload::session_anchor(&genesis, &chain[..3]);
+        assert_eq!(starting_block, chain[3].1.number);
+        assert_eq!(anchor.hash(), chain[3].1.parent_hash);
+    }
+
+    #[test]
+    fn validate_parallel_cancelled() {
+        let consensus = TestConsensus::default();
+        let headers = make_chain(&BlockHeader::default(), 16);
+
+        let cancel = CancellationToken::new();
+        assert_eq!(
+            HeaderDownload::validate_parallel(&consensus, &headers, &cancel, None),
+            Ok(())
+        );
+
+        cancel.cancel();
+        assert_eq!(
+            HeaderDownload::validate_parallel(&consensus, &headers, &cancel, None),
+            Err(SealValidationError::Cancelled)
+        );
+    }
+
+    #[test]
+    fn validate_parallel_failures() {
+        let consensus = TestConsensus::default();
+        let mut headers = make_chain(&BlockHeader::default(), 8);
+        for i in [5, 2] {
+            headers[i].1.extra_data = BAD_SEAL.into();
+        }
+
+        let cancel = CancellationToken::new();
+        let failures = Mutex::new(Vec::new());
+        assert_eq!(
+            HeaderDownload::validate_parallel(&consensus, &headers, &cancel, Some(&failures)),
+            Err(SealValidationError::Invalid {
+                first_invalid: 2,
+                invalid_hash: headers[2].0,
+            })
+        );
+
+        let mut failures = failures.into_inner();
+        failures.sort_unstable_by_key(|(number, _, _)| *number);
+        assert_eq!(
+            failures
+                .iter()
+                .map(|(number, hash, _)| (*number, *hash))
+                .collect::<Vec<_>>(),
+            [
+                (BlockNumber(3), headers[2].0),
+                (BlockNumber(6), headers[5].0)
+            ]
+        );
+        assert!(failures
+            .iter()
+            .all(|(_, _, e)| matches!(e, DuoError::Validation(ValidationError::InvalidSeal))));
+
+        // Bad first header leaves nothing usable, with or without collecting failures
+        headers[0].1.extra_data = BAD_SEAL.into();
+        assert_eq!(
+            HeaderDownload::validate_parallel(&consensus, &headers, &cancel, None),
+            Err(SealValidationError::Invalid {
+                first_invalid: 0,
+                invalid_hash: headers[0].0,
+            })
+        );
+    }
+
+    #[test]
+    fn orphan_pool() {
+        let genesis = BlockHeader::default();
+        let chain = make_chain(&genesis, 6);
+
+        let pool = OrphanPool::new(2);
+        pool.insert(chain[4..].to_vec());
+        pool.insert(chain[2..4].to_vec());
+        assert_eq!(pool.len(), 2);
+
+        // Nothing attaches yet
+        let mut graph = ForkChoiceGraph::new();
+        graph.extend(chain[..1].to_vec());
+        assert!(pool.promote(&mut graph, genesis.hash()).is_empty());
+
+        // Bridging header arrives, both segments are promoted
+        graph.extend(chain[1..2].to_vec());
+        assert_eq!(
+            pool.promote(&mut graph, genesis.hash()),
+            (3..=6).map(BlockNumber).collect::<HashSet<_>>()
+        );
+        assert!(pool.is_empty());
+        assert_eq!(graph.chain_head(), Some(chain[5].0));
+
+        // Oldest segment is evicted
+        pool.insert(chain[1..2].to_vec());
+        pool.insert(chain[3..4].to_vec());
+        pool.insert(chain[5..].to_vec());
+        assert_eq!(pool.len(), 2);
+        assert_eq!(
+            pool.promote(&mut ForkChoiceGraph::new(), chain[0].0),
+            HashSet::new()
+        );
+        assert_eq!(
+            pool.promote(&mut ForkChoiceGraph::new(), chain[2].0),
+            [BlockNumber(4)].into_iter().collect::<HashSet<_>>()
+        );
+    }
+
+    #[test]
+    fn drop_requests_above() {
+        let requests = HeaderDownload::prepare_requests(BlockNumber(1), BlockNumber(3000));
+        assert_eq!(requests.len(), 3);
+
+        assert_eq!(
+            HeaderDownload::drop_requests_above(&requests, BlockNumber(1500)),
+            1
+        );
+        assert_eq!(
+            requests
+                .iter()
+                .map(|entry| (*entry.key(), entry.value().limit))
+                .collect::<BTreeMap<_, _>>(),
+            [(BlockNumber(1), 1024), (BlockNumber(1025), 476)]
+                .into_iter()
+                .collect()
+        );
+
+        // Tip above outstanding requests keeps them as they are
+        assert_eq!(
+            HeaderDownload::drop_requests_above(&requests, BlockNumber(3000)),
+            0
+        );
+        assert_eq!(requests.get(&BlockNumber(1025)).unwrap().limit, 476);
+    }
+
+    #[test]
+    fn record_sent() {
+        let sent_at = DashMap::new();
+        let (a, b) = ((0, H512::random()), (1, H512::random()));
+        let request = |start| HeaderRequest {
+            start: BlockId::Number(BlockNumber(start)),
+            limit: 1024,
+            ..Default::default()
+        };
+        let first = Instant::now();
+        let second = first + Duration::from_secs(3);
+
+        HeaderDownload::record_sent(
+            &sent_at,
+            first,
+            vec![
+                (request(1), HashSet::from([a, b])),
+                (request(1025), HashSet::from([b])),
+            ],
+        );
+        // Retry routed to another peer keeps the first peer's send time
+        HeaderDownload::record_sent(&sent_at, second, vec![(request(1025), HashSet::from([a]))]);
+
+        let sent = |start, (sentry_id, peer_id)| {
+            sent_at
+                .get(&(BlockNumber(start), sentry_id, peer_id))
+                .map(|e| *e)
+        };
+        assert_eq!(sent(1, a), Some(first));
+        assert_eq!(sent(1, b), Some(first));
+        assert_eq!(sent(1025, b), Some(first));
+        assert_eq!(sent(1025, a), Some(second));
+        assert_eq!(sent_at.len(), 4);
+    }
+
+    #[test]
+    fn due_requests() {
+        let requests = HeaderDownload::prepare_requests(BlockNumber(1), BlockNumber(2048));
+        let mut last_sent = HashMap::new();
+        let retry_interval = Duration::from_secs(30);
+        let start = Instant::now();
+
+        assert_eq!(
+            HeaderDownload::due_requests(&requests, &mut last_sent, retry_interval, start).len(),
+            2
+        );
+        // Freshly sent requests are not rebroadcast within the interval
+        assert!(HeaderDownload::due_requests(
+            &requests,
+            &mut last_sent,
+            retry_interval,
+            start + Duration::from_secs(5)
+        )
+        .is_empty());
+
+        // New request is sent right away, the rest waits
+        requests.insert(
+            BlockNumber(2049),
+            HeaderRequest {
+                start: BlockId::Number(BlockNumber(2049)),
+                limit: 1,
+                ..Default::default()
+            },
+        );
+        let due = HeaderDownload::due_requests(
+            &requests,
+            &mut last_sent,
+            retry_interval,
+            start + Duration::from_secs(10),
+        );
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].start, BlockId::Number(BlockNumber(2049)));
+
+        requests.remove(&BlockNumber(1));
+        assert_eq!(
+            HeaderDownload::due_requests(
+                &requests,
+                &mut last_sent,
+                retry_interval,
+                start + Duration::from_secs(30)
+            )
+            .len(),
+            1
+        );
+        assert_eq!(last_sent.len(), 2);
+    }
 
     #[test]
     fn prepare_requests() {
@@ -10,7 +10,10 @@ use crate::{
 };
 use async_trait::async_trait;
 use rayon::prelude::*;
-use std::time::{Duration, Instant};
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
 use tokio::pin;
 use tracing::*;
 
@@ -20,6 +23,8 @@ pub const SENDERS: StageId = StageId("SenderRecovery");
 #[derive(Debug)]
 pub struct SenderRecovery {
     pub batch_size: usize,
+    /// Pool to recover signatures on. Global rayon pool is used if not set.
+    pub thread_pool: Option<Arc<rayon::ThreadPool>>,
 }
 
 #[async_trait]
@@ -55,6 +60,8 @@ where
             tables::TotalTx,
             input.first_started_at.1.unwrap_or(BlockNumber(0)),
         )?;
+        let mut recovered_total = 0;
+        let mut recovery_time = Duration::ZERO;
         let done = loop {
             let mut read_again = false;
             let mut batch_txs = 0;
@@ -78,32 +85,52 @@ where
             }
 
             debug!("Recovering senders from batch of {} bodies", batch.len());
-            let recovered_senders = batch
-                .par_drain(..)
-                .filter_map(move |(block_number, txs)| {
-                    if !txs.is_empty() {
-                        let senders = txs
-                            .into_iter()
-                            .map(|encoded_tx| {
-                                let tx = ErasedTable::<tables::BlockTransaction>::decode_value(
-                                    &encoded_tx,
-                                )?;
-                                let sender = tx.recover_sender()?;
-                                Ok::<_, anyhow::Error>(sender)
-                            })
-                            .collect::<anyhow::Result<Vec<Address>>>();
-
-                        Some(senders.map(|senders| {
-                            (
-                                ErasedTable::<tables::TxSender>::encode_key(block_number).to_vec(),
-                                senders.encode(),
-                            )
-                        }))
-                    } else {
-                        None
-                    }
-                })
-                .collect::<anyhow::Result<Vec<_>>>()?;
+            let recovery_started_at = Instant::now();
+            let recover = || {
+                batch
+                    .par_drain(..)
+                    .filter_map(move |(block_number, txs)| {
+                        if !txs.is_empty() {
+                            let senders = txs
+                                .into_iter()
+                                .map(|encoded_tx| {
+                                    let tx = ErasedTable::<tables::BlockTransaction>::decode_value(
+                                        &encoded_tx,
+                                    )?;
+                                    let sender = tx.recover_sender()?;
+                                    Ok::<_, anyhow::Error>(sender)
+                                })
+                                .collect::<anyhow::Result<Vec<Address>>>();
+
+                            Some(senders.map(|senders| {
+                                (
+                                    ErasedTable::<tables::TxSender>::encode_key(block_number)
+                                        .to_vec(),
+                                    senders.encode(),
+                                )
+                            }))
+                        } else {
+                            None
+                        }
+                    })
+                    .collect::<anyhow::Result<Vec<_>>>()
+            };
+            let recovered_senders = if let Some(pool) = &self.thread_pool {
+                pool.install(recover)
+            } else {
+                recover()
+            }?;
+            let batch_recovery_time = recovery_started_at.elapsed();
+            recovered_total += batch_txs;
+            recovery_time += batch_recovery_time;
+            if batch_txs > 0 {
+                debug!(
+                    "Recovered {} senders in {}, {:.0} signatures/sec",
+                    batch_txs,
+                    format_duration(batch_recovery_time, true),
+                    batch_txs as f64 / batch_recovery_time.as_secs_f64()
+                );
+            }
 
             debug!("Inserting recovered senders");
             for (db_key, db_value) in recovered_senders {
@@ -118,6 +145,13 @@ where
             let elapsed = now - started_at;
             if elapsed > Duration::from_secs(30) {
                 let mut format_string = format!("Extracted senders from block {}", highest_block);
+                if recovered_total > 0 && !recovery_time.is_zero() {
+                    format_string = format!(
+                        "{}, {:.0} signatures/sec",
+                        format_string,
+                        recovered_total as f64 / recovery_time.as_secs_f64()
+                    );
+                }
 
                 if let Some(started_at_txnum) = started_at_txnum {
                     let current_txnum = tx.get(tables::TotalTx, highest_block)?;
@@ -191,6 +225,21 @@ mod tests {
 
     #[tokio::test]
     async fn recover_senders() {
+        check_recover_senders(None).await;
+    }
+
+    #[tokio::test]
+    async fn recover_senders_thread_pool() {
+        check_recover_senders(Some(Arc::new(
+            rayon::ThreadPoolBuilder::new()
+                .num_threads(2)
+                .build()
+                .unwrap(),
+        )))
+        .await;
+    }
+
+    async fn check_recover_senders(thread_pool: Option<Arc<rayon::ThreadPool>>) {
         let db = new_mem_chaindata().unwrap();
         let mut tx = db.begin_mutable().unwrap();
 
@@ -204,6 +253,7 @@ mod tests {
             base_tx_id: 1.into(),
             tx_amount: 2,
             ommers: Default::default(),
+            withdrawals: None,
         };
 
         let tx1_1 = MessageWithSignature {
@@ -254,6 +304,7 @@ mod tests {
             base_tx_id: 3.into(),
             tx_amount: 3,
             ommers: Default::default(),
+            withdrawals: None,
         };
 
         let tx2_1 = MessageWithSignature {
@@ -326,6 +377,7 @@ mod tests {
             base_tx_id: 6.into(),
             tx_amount: 0,
             ommers: Default::default(),
+            withdrawals: None,
         };
 
         let hash1 = H256::random();
@@ -345,6 +397,7 @@ mod tests {
 
         let mut stage = SenderRecovery {
             batch_size: 500_000,
+            thread_pool,
         };
 
         let stage_input = StageInput {
@@ -172,6 +172,7 @@ mod tests {
             base_tx_id: 1.into(),
             tx_amount: 2,
             ommers: Default::default(),
+            withdrawals: None,
         };
 
         let tx1_1 = MessageWithSignature {
@@ -224,6 +225,7 @@ mod tests {
             base_tx_id: 3.into(),
             tx_amount: 3,
             ommers: Default::default(),
+            withdrawals: None,
         };
 
         let tx2_1 = MessageWithSignature {
@@ -302,6 +304,7 @@ mod tests {
             base_tx_id: 6.into(),
             tx_amount: 0,
             ommers: Default::default(),
+            withdrawals: None,
         };
 
         chain::storage_body::write(&tx, 1, &block1).unwrap();
@@ -386,6 +389,7 @@ mod tests {
             base_tx_id: 1.into(),
             tx_amount: 2,
             ommers: Default::default(),
+            withdrawals: None,
         };
 
         let tx1_1 = MessageWithSignature {
@@ -438,6 +442,7 @@ mod tests {
             base_tx_id: 3.into(),
             tx_amount: 3,
             ommers: Default::default(),
+            withdrawals: None,
         };
 
         let tx2_1 = MessageWithSignature {
@@ -516,6 +521,7 @@ mod tests {
             base_tx_id: 6.into(),
             tx_amount: 0,
             ommers: Default::default(),
+            withdrawals: None,
         };
 
         chain::storage_body::write(&tx, 1, &block1).unwrap();
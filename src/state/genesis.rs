@@ -144,6 +144,7 @@ where
             base_tx_id: 0.into(),
             tx_amount: 0,
             ommers: Default::default(),
+            withdrawals: None,
         },
     )?;
 